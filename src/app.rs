@@ -1,70 +1,331 @@
-use freedesktop_desktop_entry::{get_languages_from_env, DesktopEntry};
-use std::{path::PathBuf, process::Command};
-use tracing::debug;
+use crate::cache;
+use crate::conf::{AliasConf, AppsConf};
+use freedesktop_desktop_entry::{default_paths, get_languages_from_env, DesktopEntry};
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use gtk::glib;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use tracing::{debug, warn};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AppEntry {
+    /// desktop file id, the file name without the `.desktop` extension
+    pub id: String,
     pub exec: String,
     pub need_terminal: bool,
     pub icon: String,
     pub name: String,
     pub description: String,
-    pub _path: PathBuf,
+    pub path: PathBuf,
+    /// `MimeType=`, the types the app can open
+    pub mime_types: Vec<String>,
+    /// user defined shortcuts matching this entry, never cached
+    pub aliases: Vec<String>,
+    /// `NoDisplay=true`, the entry opens files and links but is never
+    /// listed
+    pub no_display: bool,
 }
 
-const PATTERNS: [&str; 13] = [
-    "%f", "%F", "%u", "%U", "%d", "%D", "%n", "%N", "%i", "%c", "%k", "%v", "%m",
-];
+impl AppEntry {
+    /// Expands the field codes of the `Exec` line, `uris` fill `%u` and
+    /// `%U`, and `%f` and `%F` once turned into local paths.
+    pub fn expand_exec(&self, uris: &[String]) -> String {
+        let quote =
+            |s: &dyn AsRef<std::ffi::OsStr>| glib::shell_quote(s).to_string_lossy().to_string();
+        let paths: Vec<String> = uris
+            .iter()
+            .filter_map(|uri| glib::filename_from_uri(uri).ok())
+            .map(|(path, _)| quote(&path))
+            .collect();
+        let uris: Vec<String> = uris.iter().map(|uri| quote(uri)).collect();
 
-fn get_exec(exec: &str) -> String {
-    let mut res = exec.to_string();
-    PATTERNS.iter().for_each(|f| {
-        res = res.replace(*f, "");
-    });
-    res.trim().to_string()
-}
+        let mut exec = String::with_capacity(self.exec.len());
+        let mut chars = self.exec.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                exec.push(c);
+                continue;
+            }
+            match chars.peek() {
+                Some('%') => exec.push('%'),
+                Some('f') => exec.push_str(paths.first().map_or("", |path| path)),
+                Some('F') => exec.push_str(&paths.join(" ")),
+                Some('u') => exec.push_str(uris.first().map_or("", |uri| uri)),
+                Some('U') => exec.push_str(&uris.join(" ")),
+                Some('i') if !self.icon.is_empty() => {
+                    exec.push_str(&format!("--icon {}", quote(&self.icon)))
+                }
+                Some('c') => exec.push_str(&quote(&self.name)),
+                Some('k') => exec.push_str(&quote(&self.path)),
+                // deprecated codes expand to nothing
+                Some('i' | 'd' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+                // not a field code, `date +%s` in an alias for instance
+                _ => {
+                    exec.push('%');
+                    continue;
+                }
+            }
+            chars.next();
+        }
+        exec.trim().to_string()
+    }
 
-impl AppEntry {
-    pub fn launch(&self, term: String, term_launch_args: Vec<String>) {
-        let exec = get_exec(&self.exec);
-        debug!("{exec:?}");
+    /// Runs the app, with `uris` as the files or URLs to open.
+    pub fn launch(&self, term: String, term_launch_args: Vec<String>, uris: &[String]) {
+        let exec = self.expand_exec(uris);
         if self.need_terminal {
-            let mut args = term_launch_args;
-            args.push(exec);
-            let _ = Command::new("sh")
-                .arg("-c")
-                .arg(format!("{term} {}", args.join(" ")))
-                .spawn();
+            launch_in_terminal(&term, &term_launch_args, &exec);
         } else {
-            let _ = Command::new("sh").arg("-c").arg(exec).spawn();
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(exec);
+            spawn_detached(command);
         }
     }
 }
 
-pub fn collect_apps() -> Vec<AppEntry> {
-    let locales = get_languages_from_env();
-    freedesktop_desktop_entry::Iter::new(freedesktop_desktop_entry::default_paths())
-        .into_iter()
-        .filter_map(|p| {
-            if let Ok(entry) = DesktopEntry::from_path(p.clone(), Some(&locales)) {
-                if entry.no_display() {
-                    return None;
-                } else {
-                    return Some(AppEntry {
-                        exec: entry.exec().unwrap_or_default().to_string(),
-                        need_terminal: entry.terminal(),
-                        icon: entry
-                            .icon()
-                            .unwrap_or("application-x-executable")
-                            .to_string(),
-                        name: entry.name(&locales).unwrap_or_default().to_string(),
-                        description: entry.comment(&locales).unwrap_or_default().to_string(),
-                        _path: p,
-                    });
+/// `term <term_launch_args> sh -c <exec>`, no shell parses the line before
+/// the terminal so `exec` reaches it as a single argument.
+fn terminal_command(term: &str, term_launch_args: &[String], exec: &str) -> Command {
+    let mut command = Command::new(term);
+    command
+        .args(term_launch_args.iter().filter(|arg| !arg.is_empty()))
+        .args(["sh", "-c", exec]);
+    command
+}
+
+/// Runs the shell command `exec` in `term`.
+pub fn launch_in_terminal(term: &str, term_launch_args: &[String], exec: &str) {
+    spawn_detached(terminal_command(term, term_launch_args, exec));
+}
+
+/// Starts `command` in a process group of its own, it outlives seekr and
+/// keeps quiet.
+pub fn spawn_detached(mut command: Command) {
+    debug!("{command:?}");
+    let spawned = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn();
+    match spawned {
+        // reaped once it exits, no zombies pile up while seekr keeps running
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("unable to run {command:?}: {e}"),
+    }
+}
+
+/// Pinned and hidden apps of the `[apps]` config section, patterns are
+/// globs matched against desktop file ids.
+pub struct AppFilter {
+    pinned: Vec<GlobMatcher>,
+    hidden: GlobSet,
+}
+
+fn id_glob(pattern: &str) -> Option<Glob> {
+    let pattern = pattern.strip_suffix(".desktop").unwrap_or(pattern);
+    match Glob::new(pattern) {
+        Ok(glob) => Some(glob),
+        Err(e) => {
+            warn!("invalid app pattern `{pattern}`: {e}");
+            None
+        }
+    }
+}
+
+impl AppFilter {
+    pub fn new(conf: &AppsConf) -> Self {
+        let mut hidden = GlobSetBuilder::new();
+        for glob in conf.hidden.iter().filter_map(|p| id_glob(p)) {
+            hidden.add(glob);
+        }
+
+        Self {
+            pinned: conf
+                .pinned
+                .iter()
+                .filter_map(|p| id_glob(p))
+                .map(|glob| glob.compile_matcher())
+                .collect(),
+            hidden: hidden.build().unwrap_or_else(|_| GlobSet::empty()),
+        }
+    }
+
+    pub fn is_hidden(&self, entry: &AppEntry) -> bool {
+        self.hidden.is_match(&entry.id)
+    }
+
+    /// Position of the first pinned pattern matching `entry`.
+    pub fn pinned_rank(&self, entry: &AppEntry) -> Option<usize> {
+        self.pinned.iter().position(|glob| glob.is_match(&entry.id))
+    }
+}
+
+/// Turns the `[alias.*]` sections into aliases of existing entries or into
+/// synthetic entries for custom commands.
+fn apply_aliases(entries: &mut Vec<AppEntry>, aliases: &[AliasConf]) {
+    for alias in aliases {
+        match (&alias.exec, &alias.app) {
+            (Some(exec), _) => entries.push(AppEntry {
+                id: format!("alias.{}", alias.alias),
+                exec: exec.clone(),
+                need_terminal: alias.terminal,
+                icon: alias.icon.clone().unwrap_or_else(|| {
+                    if alias.terminal {
+                        "utilities-terminal".to_string()
+                    } else {
+                        "application-x-executable".to_string()
+                    }
+                }),
+                name: alias.name.clone().unwrap_or_else(|| alias.alias.clone()),
+                description: alias.description.clone().unwrap_or_else(|| exec.clone()),
+                path: PathBuf::new(),
+                mime_types: vec![],
+                aliases: vec![alias.alias.clone()],
+                no_display: false,
+            }),
+            (None, Some(app)) => {
+                let target = entries.iter_mut().find(|entry| entry.id == *app);
+                match target {
+                    Some(entry) => entry.aliases.push(alias.alias.clone()),
+                    None => warn!("alias `{}`: no app with id `{app}`", alias.alias),
                 }
             }
+            (None, None) => warn!("alias `{}` needs either `exec` or `app`", alias.alias),
+        }
+    }
+}
 
-            None
+pub fn collect_apps(filter: &AppFilter, aliases: &[AliasConf]) -> Vec<AppEntry> {
+    let locales = get_languages_from_env();
+    let key = cache::CacheKey::current(default_paths(), &locales);
+    let mut entries = match cache::load_apps(&key) {
+        Some(entries) => entries,
+        None => {
+            let entries = parse_apps(&locales);
+            cache::store_apps(&key, &entries);
+            entries
+        }
+    };
+
+    apply_aliases(&mut entries, aliases);
+    entries.retain(|entry| !filter.is_hidden(entry));
+    entries
+}
+
+fn parse_apps(locales: &[String]) -> Vec<AppEntry> {
+    freedesktop_desktop_entry::Iter::new(default_paths())
+        .filter_map(|p| {
+            let entry = DesktopEntry::from_path(p.clone(), Some(locales)).ok()?;
+            Some(AppEntry {
+                id: entry.id().to_string(),
+                exec: entry.exec().unwrap_or_default().to_string(),
+                need_terminal: entry.terminal(),
+                icon: entry
+                    .icon()
+                    .unwrap_or("application-x-executable")
+                    .to_string(),
+                name: entry.name(locales).unwrap_or_default().to_string(),
+                description: entry.comment(locales).unwrap_or_default().to_string(),
+                path: p,
+                mime_types: entry
+                    .mime_type()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|mime_type| !mime_type.is_empty())
+                    .map(|mime_type| mime_type.to_string())
+                    .collect(),
+                aliases: vec![],
+                no_display: entry.no_display(),
+            })
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(exec: &str) -> AppEntry {
+        AppEntry {
+            id: "org.example.Viewer".to_string(),
+            exec: exec.to_string(),
+            need_terminal: false,
+            icon: "viewer".to_string(),
+            name: "Image Viewer".to_string(),
+            description: String::new(),
+            path: PathBuf::from("/usr/share/applications/org.example.Viewer.desktop"),
+            mime_types: vec![],
+            aliases: vec![],
+            no_display: false,
+        }
+    }
+
+    fn uris() -> Vec<String> {
+        vec![
+            "file:///tmp/a%20b.png".to_string(),
+            "https://example.com/c.png?x=1&y=2".to_string(),
+            "file:///tmp/it's.png".to_string(),
+        ]
+    }
+
+    #[test]
+    fn expand_files() {
+        let uris = uris();
+        assert_eq!(
+            entry("viewer %f").expand_exec(&uris),
+            "viewer '/tmp/a b.png'"
+        );
+        // remote uris have no path
+        assert_eq!(
+            entry("viewer %F").expand_exec(&uris),
+            "viewer '/tmp/a b.png' '/tmp/it'\\''s.png'"
+        );
+        assert_eq!(entry("viewer %f").expand_exec(&[]), "viewer");
+    }
+
+    #[test]
+    fn expand_uris() {
+        let uris = uris();
+        assert_eq!(
+            entry("viewer %u").expand_exec(&uris),
+            "viewer 'file:///tmp/a%20b.png'"
+        );
+        assert_eq!(
+            entry("viewer --new %U").expand_exec(&uris),
+            "viewer --new 'file:///tmp/a%20b.png' 'https://example.com/c.png?x=1&y=2' \
+             'file:///tmp/it'\\''s.png'"
+        );
+        assert_eq!(entry("viewer %U").expand_exec(&[]), "viewer");
+    }
+
+    #[test]
+    fn expand_other_codes() {
+        assert_eq!(
+            entry("viewer %i %c %k").expand_exec(&[]),
+            "viewer --icon 'viewer' 'Image Viewer' \
+             '/usr/share/applications/org.example.Viewer.desktop'"
+        );
+        let no_icon = AppEntry {
+            icon: String::new(),
+            ..entry("viewer %i")
+        };
+        assert_eq!(no_icon.expand_exec(&[]), "viewer");
+        assert_eq!(entry("viewer %d %D %n %N %v %m").expand_exec(&[]), "viewer");
+        assert_eq!(entry("printf 100%%").expand_exec(&[]), "printf 100%");
+        // not field codes
+        assert_eq!(entry("date +%s%").expand_exec(&[]), "date +%s%");
+    }
+
+    #[test]
+    fn terminal_arguments() {
+        let exec = "vim 'my notes.txt'; echo \"$HOME\"";
+        let command = terminal_command("kitty", &["-e".to_string(), String::new()], exec);
+        assert_eq!(command.get_program(), "kitty");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-e", "sh", "-c", exec]);
+    }
+}
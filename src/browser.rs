@@ -0,0 +1,503 @@
+use crate::app::{self, AppEntry};
+use crate::conf::BrowsersConf;
+use gtk::{gio, glib};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, warn};
+
+// history rows read per profile, the most visited ones
+const HISTORY_LIMIT: usize = 2000;
+// chromium counts microseconds since 1601, firefox since 1970
+const CHROMIUM_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Browser {
+    Firefox,
+    Chromium,
+    Chrome,
+    Brave,
+}
+
+impl Browser {
+    const ALL: [Browser; 4] = [
+        Browser::Firefox,
+        Browser::Chromium,
+        Browser::Chrome,
+        Browser::Brave,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Browser::Firefox => "Firefox",
+            Browser::Chromium => "Chromium",
+            Browser::Chrome => "Google Chrome",
+            Browser::Brave => "Brave",
+        }
+    }
+
+    /// Where the profiles are, relative to the home directory.
+    fn root(&self) -> &'static str {
+        match self {
+            Browser::Firefox => ".mozilla/firefox",
+            Browser::Chromium => ".config/chromium",
+            Browser::Chrome => ".config/google-chrome",
+            Browser::Brave => ".config/BraveSoftware/Brave-Browser",
+        }
+    }
+
+    /// Desktop file ids the browser usually installs.
+    fn desktop_ids(&self) -> &'static [&'static str] {
+        match self {
+            Browser::Firefox => &["firefox", "org.mozilla.firefox", "firefox-esr"],
+            Browser::Chromium => &["chromium", "chromium-browser", "org.chromium.Chromium"],
+            Browser::Chrome => &["google-chrome", "com.google.Chrome"],
+            Browser::Brave => &["brave-browser", "com.brave.Browser"],
+        }
+    }
+
+    fn command(&self) -> &'static str {
+        match self {
+            Browser::Firefox => "firefox",
+            Browser::Chromium => "chromium",
+            Browser::Chrome => "google-chrome-stable",
+            Browser::Brave => "brave-browser",
+        }
+    }
+
+    /// The installed app of the browser.
+    pub fn app<'a>(&self, entries: &'a [AppEntry]) -> Option<&'a AppEntry> {
+        self.desktop_ids()
+            .iter()
+            .find_map(|id| entries.iter().find(|entry| entry.id == *id))
+    }
+}
+
+/// A bookmark or a visited page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub title: String,
+    pub url: String,
+    pub browser: Browser,
+    pub bookmarked: bool,
+    pub visits: i64,
+    /// unix time of the last visit, 0 for bookmarks never visited
+    pub visited: i64,
+    /// the app of `browser`, filled in once the page is a result
+    pub handler: Option<AppEntry>,
+}
+
+impl Page {
+    /// Opens the page in the browser it comes from.
+    pub fn open(&self, term: String, term_launch_args: Vec<String>) {
+        if let Some(handler) = &self.handler {
+            handler.launch(term, term_launch_args, std::slice::from_ref(&self.url));
+            return;
+        }
+        if let Some(program) = glib::find_program_in_path(self.browser.command()) {
+            let mut command = Command::new(program);
+            command.arg(&self.url);
+            app::spawn_detached(command);
+            return;
+        }
+        if let Err(e) =
+            gio::AppInfo::launch_default_for_uri(&self.url, None::<&gio::AppLaunchContext>)
+        {
+            warn!("unable to open {}: {e}", self.url);
+        }
+    }
+}
+
+/// Bookmarks and history of every installed browser, bookmarks first.
+pub fn load(conf: &BrowsersConf) -> Vec<Page> {
+    let home = PathBuf::from(std::env::var("HOME").unwrap_or_default());
+    let mut pages = vec![];
+    for browser in Browser::ALL {
+        let root = home.join(browser.root());
+        if !root.is_dir() {
+            continue;
+        }
+        let found = match browser {
+            Browser::Firefox => firefox_pages(&root, conf),
+            _ => chromium_pages(browser, &root, conf),
+        };
+        debug!("read {} pages of {}", found.len(), browser.name());
+        pages.extend(found);
+    }
+    dedup(pages)
+}
+
+/// Keeps a single page per url, a bookmark over a visit.
+fn dedup(mut pages: Vec<Page>) -> Vec<Page> {
+    pages.sort_by_key(|page| (!page.bookmarked, std::cmp::Reverse(page.visits)));
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut kept: Vec<Page> = vec![];
+    for page in pages {
+        match seen.get(&page.url) {
+            Some(&i) => {
+                kept[i].visits = kept[i].visits.max(page.visits);
+                kept[i].visited = kept[i].visited.max(page.visited);
+            }
+            None => {
+                seen.insert(page.url.clone(), kept.len());
+                kept.push(page);
+            }
+        }
+    }
+    kept
+}
+
+/// An empty `profiles` list selects them all, otherwise a profile is picked
+/// by its directory or by its display name.
+fn selected(conf: &BrowsersConf, dir: &str, name: Option<&str>) -> bool {
+    conf.profiles.is_empty()
+        || conf
+            .profiles
+            .iter()
+            .any(|wanted| wanted == dir || Some(wanted.as_str()) == name)
+}
+
+/// Profile directories listed by `profiles.ini`, with their names.
+fn firefox_profiles(root: &Path) -> Vec<(PathBuf, String)> {
+    let Ok(data) = std::fs::read_to_string(root.join("profiles.ini")) else {
+        return vec![];
+    };
+
+    let mut profiles = vec![];
+    let mut current: Option<(Option<String>, bool, String)> = None;
+    let mut push = |profile: Option<(Option<String>, bool, String)>| {
+        if let Some((Some(path), relative, name)) = profile {
+            let dir = if relative {
+                root.join(path)
+            } else {
+                PathBuf::from(path)
+            };
+            profiles.push((dir, name));
+        }
+    };
+    for item in ini_roundtrip::Parser::new(&data) {
+        match item {
+            ini_roundtrip::Item::Section { name, .. } => {
+                push(current.take());
+                if name.starts_with("Profile") {
+                    current = Some((None, true, String::new()));
+                }
+            }
+            ini_roundtrip::Item::Property {
+                key,
+                val: Some(val),
+                ..
+            } => {
+                if let Some((path, relative, name)) = &mut current {
+                    match key {
+                        "Path" => *path = Some(val.to_string()),
+                        "IsRelative" => *relative = val == "1",
+                        "Name" => *name = val.to_string(),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    push(current);
+    profiles
+}
+
+fn firefox_pages(root: &Path, conf: &BrowsersConf) -> Vec<Page> {
+    let mut pages = vec![];
+    for (dir, name) in firefox_profiles(root) {
+        let dir_name = dir.file_name().unwrap_or_default().to_string_lossy();
+        if !selected(conf, &dir_name, Some(&name)) {
+            continue;
+        }
+        let places = dir.join("places.sqlite");
+        if !places.exists() {
+            continue;
+        }
+        match with_copy(&places, |db| read_places(db, conf.history)) {
+            Ok(found) => pages.extend(found),
+            Err(e) => warn!("{}: {e}", places.display()),
+        }
+    }
+    pages
+}
+
+fn read_places(db: &Connection, history: bool) -> rusqlite::Result<Vec<Page>> {
+    let page = |bookmarked: bool| {
+        move |row: &rusqlite::Row| {
+            Ok(Page {
+                title: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                url: row.get(1)?,
+                browser: Browser::Firefox,
+                bookmarked,
+                visits: row.get::<_, Option<i64>>(2)?.unwrap_or(0),
+                visited: row.get::<_, Option<i64>>(3)?.unwrap_or(0) / 1_000_000,
+                handler: None,
+            })
+        }
+    };
+
+    // `place:` urls are saved searches and smart folders
+    let mut pages: Vec<Page> = db
+        .prepare(
+            "SELECT COALESCE(NULLIF(b.title, ''), p.title), p.url, p.visit_count, p.last_visit_date
+             FROM moz_bookmarks b JOIN moz_places p ON b.fk = p.id
+             WHERE b.type = 1 AND p.url NOT LIKE 'place:%'",
+        )?
+        .query_map([], page(true))?
+        .collect::<rusqlite::Result<_>>()?;
+    if history {
+        let visited = db
+            .prepare(
+                "SELECT title, url, visit_count, last_visit_date FROM moz_places
+                 WHERE visit_count > 0 AND hidden = 0 AND url NOT LIKE 'place:%'
+                 ORDER BY frecency DESC LIMIT ?1",
+            )?
+            .query_map([HISTORY_LIMIT as i64], page(false))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        pages.extend(visited);
+    }
+    Ok(pages)
+}
+
+/// Profile directories of a chromium based browser, with the names given
+/// in `Local State`.
+fn chromium_profiles(root: &Path) -> Vec<(PathBuf, Option<String>)> {
+    let names: HashMap<String, String> = std::fs::read_to_string(root.join("Local State"))
+        .ok()
+        .and_then(|data| serde_json::from_str::<serde_json::Value>(&data).ok())
+        .and_then(|state| {
+            let cache = state.get("profile")?.get("info_cache")?.as_object()?;
+            Some(
+                cache
+                    .iter()
+                    .filter_map(|(dir, info)| {
+                        Some((dir.clone(), info.get("name")?.as_str()?.to_string()))
+                    })
+                    .collect(),
+            )
+        })
+        .unwrap_or_default();
+
+    let Ok(read_dir) = std::fs::read_dir(root) else {
+        return vec![];
+    };
+    let mut profiles: Vec<(PathBuf, Option<String>)> = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|dir| dir.join("Bookmarks").exists() || dir.join("History").exists())
+        .map(|dir| {
+            let name = names
+                .get(&*dir.file_name().unwrap_or_default().to_string_lossy())
+                .cloned();
+            (dir, name)
+        })
+        .collect();
+    profiles.sort();
+    profiles
+}
+
+fn chromium_pages(browser: Browser, root: &Path, conf: &BrowsersConf) -> Vec<Page> {
+    let mut pages = vec![];
+    for (dir, name) in chromium_profiles(root) {
+        let dir_name = dir.file_name().unwrap_or_default().to_string_lossy();
+        if !selected(conf, &dir_name, name.as_deref()) {
+            continue;
+        }
+
+        let bookmarks = dir.join("Bookmarks");
+        match std::fs::read_to_string(&bookmarks) {
+            Ok(data) => match serde_json::from_str::<serde_json::Value>(&data) {
+                Ok(json) => read_bookmarks(browser, &json, &mut pages),
+                Err(e) => warn!("{}: {e}", bookmarks.display()),
+            },
+            Err(e) => debug!("{}: {e}", bookmarks.display()),
+        }
+
+        let history = dir.join("History");
+        if conf.history && history.exists() {
+            match with_copy(&history, |db| read_history(browser, db)) {
+                Ok(found) => pages.extend(found),
+                Err(e) => warn!("{}: {e}", history.display()),
+            }
+        }
+    }
+    pages
+}
+
+/// Walks the `roots` of a `Bookmarks` file.
+fn read_bookmarks(browser: Browser, json: &serde_json::Value, pages: &mut Vec<Page>) {
+    fn walk(browser: Browser, node: &serde_json::Value, pages: &mut Vec<Page>) {
+        match node.get("type").and_then(|kind| kind.as_str()) {
+            Some("url") => {
+                let field = |key: &str| node.get(key).and_then(|val| val.as_str());
+                if let Some(url) = field("url") {
+                    pages.push(Page {
+                        title: field("name").unwrap_or_default().to_string(),
+                        url: url.to_string(),
+                        browser,
+                        bookmarked: true,
+                        visits: 0,
+                        visited: 0,
+                        handler: None,
+                    });
+                }
+            }
+            _ => {
+                for child in node
+                    .get("children")
+                    .and_then(|children| children.as_array())
+                    .into_iter()
+                    .flatten()
+                {
+                    walk(browser, child, pages);
+                }
+            }
+        }
+    }
+
+    let roots = json.get("roots").and_then(|roots| roots.as_object());
+    for root in roots.into_iter().flat_map(|roots| roots.values()) {
+        walk(browser, root, pages);
+    }
+}
+
+fn read_history(browser: Browser, db: &Connection) -> rusqlite::Result<Vec<Page>> {
+    db.prepare(
+        "SELECT title, url, visit_count, last_visit_time FROM urls
+         WHERE hidden = 0 ORDER BY visit_count DESC LIMIT ?1",
+    )?
+    .query_map([HISTORY_LIMIT as i64], |row| {
+        let time: i64 = row.get::<_, Option<i64>>(3)?.unwrap_or(0);
+        Ok(Page {
+            title: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+            url: row.get(1)?,
+            browser,
+            bookmarked: false,
+            visits: row.get::<_, Option<i64>>(2)?.unwrap_or(0),
+            visited: (time / 1_000_000 - CHROMIUM_EPOCH_OFFSET).max(0),
+            handler: None,
+        })
+    })?
+    .collect()
+}
+
+/// Runs `read` on a copy of the database, browsers keep theirs locked
+/// while running. The write-ahead log holds the latest changes, it is
+/// copied along. The copy lives in a private directory, removed with
+/// whatever sqlite left next to it.
+fn with_copy<T>(
+    database: &Path,
+    read: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+) -> Result<T, String> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(std::env::temp_dir);
+    let dir = tempfile::Builder::new()
+        .prefix("seekr-")
+        .permissions(std::fs::Permissions::from_mode(0o700))
+        .tempdir_in(runtime_dir)
+        .map_err(|e| e.to_string())?;
+    let copy = dir.path().join(database.file_name().unwrap_or_default());
+    let wal = |path: &Path| PathBuf::from(format!("{}-wal", path.display()));
+
+    std::fs::copy(database, &copy).map_err(|e| e.to_string())?;
+    let _ = std::fs::copy(wal(database), wal(&copy));
+    Connection::open_with_flags(&copy, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|db| read(&db))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures(browser: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/browsers")
+            .join(browser)
+    }
+
+    fn urls(pages: &[Page]) -> Vec<(&str, bool)> {
+        pages
+            .iter()
+            .map(|page| (page.url.as_str(), page.bookmarked))
+            .collect()
+    }
+
+    #[test]
+    fn private_copy() {
+        let places = fixtures("firefox").join("abcd.default-release/places.sqlite");
+        let copy = with_copy(&places, |db| {
+            let copy = PathBuf::from(db.path().unwrap_or_default());
+            let dir = copy.parent().unwrap().to_path_buf();
+            let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+            assert_ne!(copy, places);
+            Ok(dir)
+        })
+        .unwrap();
+        assert!(!copy.exists());
+    }
+
+    #[test]
+    fn firefox() {
+        let pages = dedup(firefox_pages(
+            &fixtures("firefox"),
+            &BrowsersConf::default(),
+        ));
+        assert_eq!(
+            urls(&pages),
+            [
+                ("https://www.rust-lang.org/", true),
+                ("https://docs.rs/", true),
+                ("https://news.ycombinator.com/", false),
+                ("https://example.com/work", false),
+            ]
+        );
+        assert_eq!(pages[0].title, "Rust");
+        assert_eq!(pages[0].visits, 12);
+        assert_eq!(pages[0].visited, 1_700_000_000);
+    }
+
+    #[test]
+    fn firefox_profile_selection() {
+        let conf = BrowsersConf {
+            profiles: vec!["work".to_string()],
+            ..Default::default()
+        };
+        let pages = firefox_pages(&fixtures("firefox"), &conf);
+        assert_eq!(urls(&pages), [("https://example.com/work", false)]);
+    }
+
+    #[test]
+    fn chromium() {
+        let conf = BrowsersConf::default();
+        let pages = dedup(chromium_pages(
+            Browser::Chromium,
+            &fixtures("chromium"),
+            &conf,
+        ));
+        assert_eq!(
+            urls(&pages),
+            [
+                ("https://github.com/", true),
+                ("https://crates.io/", true),
+                ("https://lwn.net/", false),
+            ]
+        );
+        assert_eq!(pages[0].visits, 30);
+        assert_eq!(pages[2].visited, 1_700_000_000);
+
+        let conf = BrowsersConf {
+            history: false,
+            ..Default::default()
+        };
+        let pages = chromium_pages(Browser::Chromium, &fixtures("chromium"), &conf);
+        assert_eq!(pages.len(), 2);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::app::AppEntry;
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use tracing::{debug, warn};
+
+// bump this whenever the layout of `AppEntry` or of the file below changes
+const CACHE_VERSION: u32 = 4;
+const APPS_CACHE_FILE: &str = "apps.cache";
+
+/// Everything the cached index depends on. The cache is only used when the
+/// key stored on disk is exactly the one computed at startup.
+#[derive(Debug, PartialEq)]
+pub struct CacheKey {
+    version: u32,
+    locale: String,
+    dirs: Vec<(PathBuf, u128)>,
+}
+
+impl CacheKey {
+    pub fn current(dirs: impl Iterator<Item = PathBuf>, locales: &[String]) -> Self {
+        let mut mtimes = vec![];
+        for dir in dirs {
+            collect_dir_mtimes(&dir, &mut mtimes);
+        }
+
+        Self {
+            version: CACHE_VERSION,
+            locale: locales.join(":"),
+            dirs: mtimes,
+        }
+    }
+
+    fn write_header(&self, out: &mut String) {
+        out.push_str(&format!("seekr-apps {}\n", self.version));
+        out.push_str(&format!("locale {}\n", escape(&self.locale)));
+        for (dir, mtime) in &self.dirs {
+            out.push_str(&format!("dir {mtime} {}\n", escape(&dir.to_string_lossy())));
+        }
+        out.push_str("entries\n");
+    }
+
+    fn read_header<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Option<Self> {
+        let version = lines.next()?.strip_prefix("seekr-apps ")?.parse().ok()?;
+        let locale = unescape(lines.next()?.strip_prefix("locale ")?);
+        let mut dirs = vec![];
+        loop {
+            let line = lines.next()?;
+            if line == "entries" {
+                break;
+            }
+            let (mtime, dir) = line.strip_prefix("dir ")?.split_once(' ')?;
+            dirs.push((PathBuf::from(unescape(dir)), mtime.parse().ok()?));
+        }
+
+        Some(Self {
+            version,
+            locale,
+            dirs,
+        })
+    }
+}
+
+fn collect_dir_mtimes(dir: &Path, out: &mut Vec<(PathBuf, u128)>) {
+    let Ok(meta) = std::fs::metadata(dir) else {
+        return;
+    };
+    if !meta.is_dir() {
+        return;
+    }
+
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    out.push((dir.to_path_buf(), mtime));
+
+    if let Ok(read_dir) = std::fs::read_dir(dir) {
+        // symlinked directories are not followed, a link to a parent would
+        // recurse forever
+        let mut children: Vec<PathBuf> = read_dir
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+            .map(|e| e.path())
+            .collect();
+        children.sort();
+        for child in children {
+            collect_dir_mtimes(&child, out);
+        }
+    }
+}
+
+pub fn cache_dir() -> PathBuf {
+    let raw_path = std::env::var("XDG_CACHE_HOME")
+        .unwrap_or(format!("{}/.cache", std::env::var("HOME").unwrap()));
+    std::path::Path::new(&raw_path).join("seekr")
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut res = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('t') => res.push('\t'),
+                Some('n') => res.push('\n'),
+                Some(other) => res.push(other),
+                None => res.push('\\'),
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+fn encode_entry(entry: &AppEntry) -> String {
+    [
+        escape(&entry.id),
+        escape(&entry.exec),
+        (entry.need_terminal as u8).to_string(),
+        escape(&entry.icon),
+        escape(&entry.name),
+        escape(&entry.description),
+        escape(&entry.path.to_string_lossy()),
+        escape(&entry.mime_types.join(";")),
+        (entry.no_display as u8).to_string(),
+    ]
+    .join("\t")
+}
+
+fn decode_entry(line: &str) -> Option<AppEntry> {
+    let mut fields = line.split('\t');
+    let entry = AppEntry {
+        id: unescape(fields.next()?),
+        exec: unescape(fields.next()?),
+        need_terminal: fields.next()? == "1",
+        icon: unescape(fields.next()?),
+        name: unescape(fields.next()?),
+        description: unescape(fields.next()?),
+        path: PathBuf::from(unescape(fields.next()?)),
+        mime_types: unescape(fields.next()?)
+            .split(';')
+            .filter(|mime_type| !mime_type.is_empty())
+            .map(|mime_type| mime_type.to_string())
+            .collect(),
+        aliases: vec![],
+        no_display: fields.next()? == "1",
+    };
+
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(entry)
+}
+
+/// Returns the cached app index if it was built against the same `key`.
+pub fn load_apps(key: &CacheKey) -> Option<Vec<AppEntry>> {
+    let path = cache_dir().join(APPS_CACHE_FILE);
+    let mut data = String::new();
+    std::fs::File::open(&path)
+        .ok()?
+        .read_to_string(&mut data)
+        .ok()?;
+
+    let mut lines = data.lines();
+    let stored = CacheKey::read_header(&mut lines)?;
+    if &stored != key {
+        debug!("app cache is stale, rebuilding");
+        return None;
+    }
+
+    let mut entries = vec![];
+    for line in lines {
+        match decode_entry(line) {
+            Some(entry) => entries.push(entry),
+            None => {
+                warn!("{}: corrupted entry, rebuilding", path.display());
+                return None;
+            }
+        }
+    }
+
+    debug!("loaded {} apps from cache", entries.len());
+    Some(entries)
+}
+
+pub fn store_apps(key: &CacheKey, entries: &[AppEntry]) {
+    let dir = cache_dir();
+    if !dir.exists() {
+        let _ = std::fs::create_dir_all(&dir);
+    }
+
+    let mut data = String::new();
+    key.write_header(&mut data);
+    for entry in entries {
+        data.push_str(&encode_entry(entry));
+        data.push('\n');
+    }
+
+    // write next to the real file first so a crash never leaves half a cache
+    let tmp = dir.join(format!("{APPS_CACHE_FILE}.tmp"));
+    let written = std::fs::File::create(&tmp).and_then(|mut f| f.write_all(data.as_bytes()));
+    match written {
+        Ok(()) => {
+            if let Err(e) = std::fs::rename(&tmp, dir.join(APPS_CACHE_FILE)) {
+                warn!("unable to store app cache: {e}");
+            }
+        }
+        Err(e) => warn!("unable to store app cache: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trip() {
+        for field in [
+            "plain",
+            "tab\there",
+            "two\nlines",
+            "back\\slash\\t",
+            "trailing\\",
+            "",
+        ] {
+            assert_eq!(unescape(&escape(field)), field);
+            assert!(!escape(field).contains(['\t', '\n']));
+        }
+    }
+
+    #[test]
+    fn entry_round_trip() {
+        let entry = AppEntry {
+            id: "org.example.Editor".to_string(),
+            exec: "editor --new-window %F".to_string(),
+            need_terminal: true,
+            icon: "/opt/editor/icon\tname.png".to_string(),
+            name: "Editor".to_string(),
+            description: "Edits\nfiles".to_string(),
+            path: PathBuf::from("/usr/share/applications/org.example.Editor.desktop"),
+            mime_types: vec!["text/plain".to_string(), "text/x-rust".to_string()],
+            aliases: vec!["ed".to_string()],
+            no_display: true,
+        };
+        let decoded = decode_entry(&encode_entry(&entry)).unwrap();
+        // aliases come from the config, they are never cached
+        assert_eq!(
+            decoded,
+            AppEntry {
+                aliases: vec![],
+                ..entry
+            }
+        );
+        assert_eq!(decode_entry("too\tfew"), None);
+    }
+
+    #[test]
+    fn symlink_loops_are_not_followed() {
+        let root = tempfile::tempdir().unwrap();
+        let sub = root.path().join("sub");
+        std::fs::create_dir(&sub).unwrap();
+        std::os::unix::fs::symlink(root.path(), sub.join("loop")).unwrap();
+
+        let mut mtimes = vec![];
+        collect_dir_mtimes(root.path(), &mut mtimes);
+        let dirs: Vec<&Path> = mtimes.iter().map(|(dir, _)| dir.as_path()).collect();
+        assert_eq!(dirs, [root.path(), sub.as_path()]);
+    }
+}
@@ -0,0 +1,571 @@
+use crate::conf::CalcConf;
+use crate::integer;
+use exmex::prelude::*;
+use rust_i18n::t;
+use std::collections::HashMap;
+use tracing::warn;
+
+// an f64 holds 15 significant decimal digits reliably, the following ones are noise
+const SIGNIFICANT_DIGITS: i32 = 15;
+// deeper nested calls of user functions are most likely a recursive definition
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+/// A calculator result, ready to be displayed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Answer {
+    /// the evaluated expression, normalized
+    pub expr: String,
+    pub value: String,
+    /// `value` without digit group separators, what gets copied
+    pub plain: String,
+    /// the value of the last statement, `ans` once the answer got used
+    pub result: f64,
+    /// variables assigned by the statements, in order
+    pub assignments: Vec<(String, f64)>,
+    /// the result in every base, as `(base name, value)`, integer answers only
+    pub bases: Vec<(String, String)>,
+}
+
+/// A constant (`g = 9.81`) or a function (`hyp(a, b) = sqrt(a^2 + b^2)`)
+/// declared in the `[calc.define]` section.
+#[derive(Clone, Debug)]
+pub struct Definition {
+    pub name: String,
+    /// `None` for constants
+    pub params: Option<Vec<String>>,
+    pub body: String,
+}
+
+impl Definition {
+    pub fn parse(key: &str, body: &str) -> Option<Self> {
+        let (name, params) = match key.split_once('(') {
+            Some((name, params)) => {
+                let params: Vec<String> = params
+                    .trim_end()
+                    .strip_suffix(')')?
+                    .split(',')
+                    .map(|param| param.trim().to_string())
+                    .filter(|param| !param.is_empty())
+                    .collect();
+                if !params.iter().all(|param| is_identifier(param)) {
+                    return None;
+                }
+                (name.trim(), Some(params))
+            }
+            None => (key.trim(), None),
+        };
+
+        is_identifier(name).then(|| Self {
+            name: name.to_string(),
+            params,
+            body: body.to_string(),
+        })
+    }
+}
+
+/// Calculator state living as long as the daemon: the last used answer, the
+/// variables assigned so far and what the config declares.
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    ans: Option<f64>,
+    vars: HashMap<String, f64>,
+    constants: HashMap<String, f64>,
+    functions: HashMap<String, (Vec<String>, String)>,
+}
+
+impl Session {
+    pub fn new(conf: &CalcConf) -> Self {
+        let mut session = Self::default();
+        session.configure(conf);
+        session
+    }
+
+    /// Reloads the declared constants and functions, `ans` and the variables
+    /// are kept.
+    pub fn configure(&mut self, conf: &CalcConf) {
+        self.constants.clear();
+        self.functions.clear();
+
+        for def in &conf.definitions {
+            if let Some(params) = &def.params {
+                self.functions
+                    .insert(def.name.clone(), (params.clone(), def.body.clone()));
+            }
+        }
+        // constants may use the functions and the constants declared before them
+        for def in conf.definitions.iter().filter(|def| def.params.is_none()) {
+            match self.eval_expr(&def.body, &HashMap::new()) {
+                Some(value) => {
+                    self.constants.insert(def.name.clone(), value);
+                }
+                None => warn!("invalid calc constant `{} = {}`", def.name, def.body),
+            }
+        }
+    }
+
+    /// Keeps the result and the assignments of an answer the user picked.
+    pub fn remember(&mut self, answer: &Answer) {
+        self.vars.extend(answer.assignments.iter().cloned());
+        self.ans = Some(answer.result);
+    }
+
+    /// Evaluates `;` separated statements, each one either an expression or
+    /// a `name = expression` assignment. The answer is the last statement.
+    /// Queries calling for integers go to `integer::evaluate` instead.
+    pub fn evaluate(&self, query: &str, conf: &CalcConf) -> Option<Answer> {
+        let integer_answer =
+            integer::evaluate(query, conf, |name| self.lookup(name, &HashMap::new()));
+        if integer_answer.is_some() {
+            return integer_answer;
+        }
+
+        let mut locals = HashMap::new();
+        let mut assignments = vec![];
+        let mut result = None;
+
+        for statement in query.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let value = match split_assignment(statement) {
+                Some((name, expr)) => {
+                    let value = self.eval_expr(expr, &locals)?;
+                    locals.insert(name.to_string(), value);
+                    assignments.push((name.to_string(), value));
+                    value
+                }
+                None => self.eval_expr(statement, &locals)?,
+            };
+            result = Some(value);
+        }
+
+        let result = result?;
+        Some(Answer {
+            expr: normalize(query),
+            value: format_number(result, conf),
+            plain: format_number(
+                result,
+                &CalcConf {
+                    thousands_separator: String::new(),
+                    ..conf.clone()
+                },
+            ),
+            result,
+            assignments,
+            bases: vec![],
+        })
+    }
+
+    fn eval_expr(&self, expr: &str, locals: &HashMap<String, f64>) -> Option<f64> {
+        let expanded = self.expand(expr, 0)?;
+        let flat = exmex::FlatEx::<f64>::parse(&expanded).ok()?;
+        let values = flat
+            .var_names()
+            .iter()
+            .map(|name| self.lookup(name, locals))
+            .collect::<Option<Vec<f64>>>()?;
+        flat.eval(&values).ok()
+    }
+
+    fn lookup(&self, name: &str, locals: &HashMap<String, f64>) -> Option<f64> {
+        locals
+            .get(name)
+            .or_else(|| self.vars.get(name))
+            .or_else(|| self.constants.get(name))
+            .copied()
+            .or(if name == "ans" { self.ans } else { None })
+    }
+
+    /// Inlines the calls to user functions, with `hyp(a, b) = sqrt(a^2 + b^2)`
+    /// `hyp(3, 4)` becomes `(sqrt((3)^2 + (4)^2))`.
+    fn expand(&self, expr: &str, depth: usize) -> Option<String> {
+        if self.functions.is_empty() {
+            return Some(expr.to_string());
+        }
+        if depth > MAX_EXPANSION_DEPTH {
+            return None;
+        }
+
+        let mut res = String::with_capacity(expr.len());
+        let mut rest = expr;
+        while let Some(start) = rest.find(is_ident_char) {
+            res.push_str(&rest[..start]);
+            let token_len = rest[start..]
+                .find(|c| !is_ident_char(c))
+                .unwrap_or(rest.len() - start);
+            let (name, after) = rest[start..].split_at(token_len);
+
+            let call = self
+                .functions
+                .get(name)
+                .zip(after.trim_start().strip_prefix('('));
+            match call {
+                Some(((params, body), args)) => {
+                    let (args, after) = split_call(args)?;
+                    if args.len() != params.len() {
+                        return None;
+                    }
+                    let args = args
+                        .iter()
+                        .map(|arg| self.expand(arg, depth + 1))
+                        .collect::<Option<Vec<String>>>()?;
+                    let body = replace_idents(body, |ident| {
+                        let i = params.iter().position(|param| param == ident)?;
+                        Some(format!("({})", args[i]))
+                    });
+
+                    res.push('(');
+                    res.push_str(&self.expand(&body, depth + 1)?);
+                    res.push(')');
+                    rest = after;
+                }
+                None => {
+                    res.push_str(name);
+                    rest = after;
+                }
+            }
+        }
+        res.push_str(rest);
+
+        Some(res)
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(is_ident_char)
+}
+
+/// `x = 3` gives `("x", " 3")`, comparisons such as `x == 3` are not
+/// assignments.
+fn split_assignment(statement: &str) -> Option<(&str, &str)> {
+    let (name, expr) = statement.split_once('=')?;
+    let name = name.trim();
+    if expr.starts_with('=') || !is_identifier(name) {
+        return None;
+    }
+    Some((name, expr))
+}
+
+/// Splits the arguments of a call, `args` starts right after the opening
+/// parenthesis. Returns them with what follows the closing one.
+fn split_call(args: &str) -> Option<(Vec<&str>, &str)> {
+    let mut depth = 0;
+    let mut res = vec![];
+    let mut arg_start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                res.push(&args[arg_start..i]);
+                // `f()` has no argument rather than an empty one
+                if res.len() == 1 && res[0].trim().is_empty() {
+                    res.clear();
+                }
+                return Some((res, &args[i + 1..]));
+            }
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                res.push(&args[arg_start..i]);
+                arg_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    // unbalanced parentheses
+    None
+}
+
+/// Replaces the identifiers of `expr` for which `f` returns something.
+fn replace_idents(expr: &str, f: impl Fn(&str) -> Option<String>) -> String {
+    let mut res = String::with_capacity(expr.len());
+    let mut rest = expr;
+    while let Some(start) = rest.find(is_ident_char) {
+        res.push_str(&rest[..start]);
+        let token_len = rest[start..]
+            .find(|c| !is_ident_char(c))
+            .unwrap_or(rest.len() - start);
+        let (token, after) = rest[start..].split_at(token_len);
+        match f(token) {
+            Some(replacement) => res.push_str(&replacement),
+            None => res.push_str(token),
+        }
+        rest = after;
+    }
+    res.push_str(rest);
+
+    res
+}
+
+/// Rewrites `expr` with a single space around binary operators and none
+/// anywhere else, `2+3 *4` becomes `2 + 3 * 4`, `x=3;x^2` becomes `x = 3; x^2`.
+pub fn normalize(expr: &str) -> String {
+    let mut res = String::with_capacity(expr.len());
+    // whether the next `+`/`-` follows an operand, making it a binary operator
+    let mut after_operand = false;
+
+    for c in expr.chars().filter(|c| !c.is_whitespace()) {
+        match c {
+            '+' | '-' | '*' | '/' | '^' if after_operand => {
+                res.push(' ');
+                res.push(c);
+                res.push(' ');
+                after_operand = false;
+            }
+            '=' => {
+                res.push_str(" = ");
+                after_operand = false;
+            }
+            ',' | ';' => {
+                res.push(c);
+                res.push(' ');
+                after_operand = false;
+            }
+            '(' => {
+                res.push(c);
+                after_operand = false;
+            }
+            '+' | '-' | '*' | '/' | '^' => {
+                res.push(c);
+                after_operand = false;
+            }
+            _ => {
+                res.push(c);
+                after_operand = true;
+            }
+        }
+    }
+
+    res.trim_end().to_string()
+}
+
+fn trim_zeros(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
+
+fn group_thousands(number: &str, separator: &str) -> String {
+    let (sign, number) = match number.strip_prefix('-') {
+        Some(number) => ("-", number),
+        None => ("", number),
+    };
+    let (int, frac) = match number.split_once('.') {
+        Some((int, frac)) => (int, Some(frac)),
+        None => (number, None),
+    };
+
+    let mut grouped = String::with_capacity(number.len() + int.len() / 3 * separator.len());
+    for (i, digit) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+
+    match frac {
+        Some(frac) => format!("{sign}{grouped}.{frac}"),
+        None => format!("{sign}{grouped}"),
+    }
+}
+
+pub fn format_number(x: f64, conf: &CalcConf) -> String {
+    if x.is_nan() {
+        return t!("not_a_number").to_string();
+    }
+    if x.is_infinite() {
+        return if x > 0.0 { "∞" } else { "-∞" }.to_string();
+    }
+    // also catches -0
+    if x == 0.0 {
+        return "0".to_string();
+    }
+
+    let abs = x.abs();
+    if abs >= conf.scientific_above || abs < conf.scientific_below {
+        let decimals = conf.precision.min(SIGNIFICANT_DIGITS as usize - 1);
+        let formatted = format!("{:.*e}", decimals, x);
+        if let Some((mantissa, exponent)) = formatted.split_once('e') {
+            return format!("{}e{exponent}", trim_zeros(mantissa));
+        }
+        return formatted;
+    }
+
+    let int_digits = abs.log10().floor() as i32 + 1;
+    let decimals = conf
+        .precision
+        .min((SIGNIFICANT_DIGITS - int_digits).max(0) as usize);
+    let formatted = format!("{:.*}", decimals, x);
+    match trim_zeros(&formatted) {
+        // rounded away by the precision
+        "-0" | "0" => "0".to_string(),
+        number => group_thousands(number, &conf.thousands_separator),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(definitions: &[(&str, &str)]) -> (Session, CalcConf) {
+        let conf = CalcConf {
+            definitions: definitions
+                .iter()
+                .map(|(key, body)| Definition::parse(key, body).unwrap())
+                .collect(),
+            ..CalcConf::default()
+        };
+        (Session::new(&conf), conf)
+    }
+
+    fn value(session: &Session, conf: &CalcConf, query: &str) -> Option<String> {
+        session.evaluate(query, conf).map(|answer| answer.value)
+    }
+
+    #[test]
+    fn split_call_arguments() {
+        assert_eq!(split_call("1, 2) + 3"), Some((vec!["1", " 2"], " + 3")));
+        assert_eq!(split_call(") * 2"), Some((vec![], " * 2")));
+        assert_eq!(
+            split_call("f(1, 2), (3)) - 1"),
+            Some((vec!["f(1, 2)", " (3)"], " - 1"))
+        );
+        assert_eq!(split_call("1, (2"), None);
+    }
+
+    #[test]
+    fn replace_idents_whole_words() {
+        let swap = |ident: &str| match ident {
+            "a" => Some("(1)".to_string()),
+            "ab" => Some("(2)".to_string()),
+            _ => None,
+        };
+        assert_eq!(replace_idents("a + ab * abc", swap), "(1) + (2) * abc");
+        assert_eq!(replace_idents("sqrt(a)^2", swap), "sqrt((1))^2");
+        assert_eq!(replace_idents("", swap), "");
+    }
+
+    #[test]
+    fn expand_nested_calls() {
+        let (session, conf) = session(&[
+            ("hyp(a, b)", "sqrt(a^2 + b^2)"),
+            ("double(x)", "2 * x"),
+            ("quad(x)", "double(double(x))"),
+        ]);
+        assert_eq!(
+            session.expand("hyp(3,4)", 0).unwrap(),
+            "(sqrt((3)^2 + (4)^2))"
+        );
+        assert_eq!(value(&session, &conf, "hyp(hyp(3, 4), 12)").unwrap(), "13");
+        assert_eq!(value(&session, &conf, "quad(1 + 2)").unwrap(), "12");
+        assert_eq!(value(&session, &conf, "hyp(3)"), None);
+        assert_eq!(value(&session, &conf, "hyp(3, 4"), None);
+    }
+
+    #[test]
+    fn expand_recursion_depth() {
+        let (session, conf) = session(&[("loop(x)", "loop(x) + 1"), ("inc(x)", "x + 1")]);
+        assert_eq!(session.expand("loop(1)", 0), None);
+        assert_eq!(value(&session, &conf, "loop(1)"), None);
+
+        let nested = |depth: usize| "inc(".repeat(depth) + "0" + &")".repeat(depth);
+        assert_eq!(
+            value(&session, &conf, &nested(MAX_EXPANSION_DEPTH / 2)).unwrap(),
+            (MAX_EXPANSION_DEPTH / 2).to_string()
+        );
+        assert_eq!(
+            value(&session, &conf, &nested(MAX_EXPANSION_DEPTH + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn shadowing() {
+        let (mut session, conf) = session(&[
+            ("x", "100"),
+            ("g", "9.81"),
+            ("square(x)", "x * x"),
+            ("plus_g(g)", "g + 1"),
+        ]);
+        // parameters shadow the constants
+        assert_eq!(value(&session, &conf, "square(3)").unwrap(), "9");
+        assert_eq!(value(&session, &conf, "plus_g(1)").unwrap(), "2");
+        // assignments shadow the constants, the last one wins
+        assert_eq!(value(&session, &conf, "g = 2; g * 3").unwrap(), "6");
+        assert_eq!(value(&session, &conf, "x = 1; x = x + 1; x").unwrap(), "2");
+
+        let answer = session.evaluate("g = 10; g / 4", &conf).unwrap();
+        session.remember(&answer);
+        assert_eq!(value(&session, &conf, "g").unwrap(), "10");
+        assert_eq!(value(&session, &conf, "ans * 2").unwrap(), "5");
+    }
+
+    #[test]
+    fn format_number_precision() {
+        let conf = CalcConf::default();
+        assert_eq!(format_number(0.1 + 0.2, &conf), "0.3");
+        assert_eq!(format_number(1.0 / 3.0, &conf), "0.3333333333");
+        assert_eq!(format_number(2.5, &conf), "2.5");
+        assert_eq!(format_number(-0.0, &conf), "0");
+
+        let conf = CalcConf {
+            precision: 2,
+            ..CalcConf::default()
+        };
+        assert_eq!(format_number(std::f64::consts::PI, &conf), "3.14");
+        assert_eq!(format_number(0.001, &conf), "0");
+        assert_eq!(format_number(-0.001, &conf), "0");
+        // the 15 significant digits leave no room for decimals
+        assert_eq!(
+            format_number(123456789012345.67, &CalcConf::default()),
+            "123,456,789,012,346"
+        );
+    }
+
+    #[test]
+    fn format_number_thousands_separator() {
+        let conf = CalcConf::default();
+        assert_eq!(format_number(999.0, &conf), "999");
+        assert_eq!(format_number(1000.0, &conf), "1,000");
+        assert_eq!(format_number(-1234567.891, &conf), "-1,234,567.891");
+
+        let conf = CalcConf {
+            thousands_separator: " ".to_string(),
+            ..CalcConf::default()
+        };
+        assert_eq!(format_number(1234567.0, &conf), "1 234 567");
+
+        let conf = CalcConf {
+            thousands_separator: String::new(),
+            ..CalcConf::default()
+        };
+        assert_eq!(format_number(1234567.5, &conf), "1234567.5");
+    }
+
+    #[test]
+    fn format_number_scientific() {
+        let conf = CalcConf::default();
+        assert_eq!(format_number(1e21, &conf), "1e21");
+        assert_eq!(format_number(1e15, &conf), "1e15");
+        assert_eq!(
+            format_number(999999999999999.0, &conf),
+            "999,999,999,999,999"
+        );
+        assert_eq!(format_number(-2.5e16, &conf), "-2.5e16");
+        assert_eq!(format_number(1e-6, &conf), "0.000001");
+        assert_eq!(format_number(1.5e-7, &conf), "1.5e-7");
+        assert_eq!(format_number(1.0 / 3.0 * 1e20, &conf), "3.3333333333e19");
+    }
+
+    #[test]
+    fn format_number_special_values() {
+        let conf = CalcConf::default();
+        assert_eq!(format_number(f64::INFINITY, &conf), "∞");
+        assert_eq!(format_number(f64::NEG_INFINITY, &conf), "-∞");
+        assert_eq!(format_number(f64::NAN, &conf), t!("not_a_number"));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::conf;
+
+const USAGE: &str = "\
+usage: seekr [COMMAND]
+
+Without a command seekr starts, or shows the running instance.
+
+commands:
+  pin <ID>      always list the app first, <ID> is a desktop file id or a glob
+  unpin <ID>    remove <ID> from the pinned apps
+  hide <ID>     never list the app
+  unhide <ID>   remove <ID> from the hidden apps
+  help          print this message";
+
+/// Runs the command found in `args`, returns the exit code of the process.
+pub fn run(args: &[String]) -> i32 {
+    let (key, add) = match args.first().map(|x| x.as_str()) {
+        Some("pin") => ("pinned", true),
+        Some("unpin") => ("pinned", false),
+        Some("hide") => ("hidden", true),
+        Some("unhide") => ("hidden", false),
+        Some("help" | "-h" | "--help") => {
+            println!("{USAGE}");
+            return 0;
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return 2;
+        }
+    };
+
+    let Some(id) = args.get(1) else {
+        eprintln!("{USAGE}");
+        return 2;
+    };
+
+    let conf_path = conf::init_config_dir();
+    match conf::edit_list(&conf_path, "apps", key, id, add) {
+        Ok(true) => {
+            println!("{}: {key} updated", conf_path.display());
+            0
+        }
+        Ok(false) => {
+            println!("nothing to do, `{id}` is already up to date in {key}");
+            0
+        }
+        Err(e) => {
+            eprintln!("{}: {e}", conf_path.display());
+            1
+        }
+    }
+}
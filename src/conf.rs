@@ -1,7 +1,9 @@
+use crate::calc;
+use crate::websearch::{self, WebSearchConf};
 use rust_i18n::t;
 use std::{
     io::{Read, Write},
-    path::PathBuf,
+    path::Path,
 };
 use tracing::{debug, warn};
 
@@ -29,72 +31,368 @@ impl Default for GeneralConf {
     }
 }
 
+/// What seekr displays while the search entry is empty.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EmptyState {
+    #[default]
+    None,
+    All,
+    Frequent,
+    Pinned,
+}
+
+impl EmptyState {
+    fn parse(val: &str) -> Option<Self> {
+        match val {
+            "none" => Some(Self::None),
+            "all" => Some(Self::All),
+            "frequent" => Some(Self::Frequent),
+            "pinned" => Some(Self::Pinned),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct AppsConf {
+    pub empty_state: EmptyState,
+    /// desktop file ids or globs, pinned apps come first in that order
+    pub pinned: Vec<String>,
+    /// desktop file ids or globs never shown
+    pub hidden: Vec<String>,
+}
+
+/// A `[alias.<alias>]` section, either a custom command (`exec`) or a
+/// shortcut to an installed app (`app`).
+#[derive(Default, Clone, Debug)]
+pub struct AliasConf {
+    pub alias: String,
+    pub exec: Option<String>,
+    /// desktop file id of the aliased app
+    pub app: Option<String>,
+    pub name: Option<String>,
+    pub icon: Option<String>,
+    pub description: Option<String>,
+    pub terminal: bool,
+}
+
+/// How calculator results are displayed.
+#[derive(Clone, Debug)]
+pub struct CalcConf {
+    /// maximum number of decimals
+    pub precision: usize,
+    pub thousands_separator: String,
+    /// results at least this large are displayed in scientific notation
+    pub scientific_above: f64,
+    /// results smaller than this are displayed in scientific notation
+    pub scientific_below: f64,
+    /// also copy results to the primary selection
+    pub copy_to_primary: bool,
+    /// user constants and functions
+    pub definitions: Vec<calc::Definition>,
+}
+
+impl Default for CalcConf {
+    fn default() -> Self {
+        CalcConf {
+            precision: 10,
+            thousands_separator: ",".to_string(),
+            scientific_above: 1e15,
+            scientific_below: 1e-6,
+            copy_to_primary: false,
+            definitions: vec![],
+        }
+    }
+}
+
+/// The `[files]` section, what the file index covers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilesConf {
+    pub enabled: bool,
+    /// `~` stands for the home directory
+    pub roots: Vec<String>,
+    /// globs matched against names and full paths
+    pub ignore: Vec<String>,
+    /// also index dot files
+    pub hidden: bool,
+    /// skip what `.gitignore` files ignore
+    pub gitignore: bool,
+}
+
+impl Default for FilesConf {
+    fn default() -> Self {
+        FilesConf {
+            enabled: true,
+            roots: vec!["~".to_string()],
+            ignore: vec![],
+            hidden: false,
+            gitignore: true,
+        }
+    }
+}
+
+/// The `[locate]` section, the plocate and mlocate databases to search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocateConf {
+    pub enabled: bool,
+    /// read in order, the first one that loads wins
+    pub databases: Vec<String>,
+}
+
+impl Default for LocateConf {
+    fn default() -> Self {
+        LocateConf {
+            enabled: true,
+            databases: vec![
+                "/var/lib/plocate/plocate.db".to_string(),
+                "/var/lib/mlocate/mlocate.db".to_string(),
+            ],
+        }
+    }
+}
+
+/// The `[recent]` section, files recently opened by other apps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecentConf {
+    pub enabled: bool,
+}
+
+impl Default for RecentConf {
+    fn default() -> Self {
+        RecentConf { enabled: true }
+    }
+}
+
+/// The `[executables]` section, programs of `$PATH` without a desktop entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutablesConf {
+    pub enabled: bool,
+    /// names of the executables to run in the terminal
+    pub terminal: Vec<String>,
+}
+
+impl Default for ExecutablesConf {
+    fn default() -> Self {
+        ExecutablesConf {
+            enabled: true,
+            terminal: [
+                "htop", "btop", "top", "vim", "nvim", "nano", "less", "man", "ranger",
+            ]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+        }
+    }
+}
+
+/// The `[browsers]` section, bookmarks and history of Firefox and the
+/// chromium based browsers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BrowsersConf {
+    pub enabled: bool,
+    pub history: bool,
+    /// profile directories or names to read, all of them when empty
+    pub profiles: Vec<String>,
+}
+
+impl Default for BrowsersConf {
+    fn default() -> Self {
+        BrowsersConf {
+            enabled: true,
+            history: true,
+            profiles: vec![],
+        }
+    }
+}
+
+/// The `[ssh]` section, hosts of `~/.ssh/config` and `known_hosts`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SshConf {
+    pub enabled: bool,
+}
+
+impl Default for SshConf {
+    fn default() -> Self {
+        SshConf { enabled: true }
+    }
+}
+
+/// The `[windows]` section, open windows of sway and hyprland.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowsConf {
+    pub enabled: bool,
+}
+
+impl Default for WindowsConf {
+    fn default() -> Self {
+        WindowsConf { enabled: true }
+    }
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct Config {
     pub general: GeneralConf,
+    pub apps: AppsConf,
+    pub aliases: Vec<AliasConf>,
+    pub calc: CalcConf,
+    pub files: FilesConf,
+    pub locate: LocateConf,
+    pub recent: RecentConf,
+    pub executables: ExecutablesConf,
+    pub browsers: BrowsersConf,
+    pub ssh: SshConf,
+    pub windows: WindowsConf,
+    pub websearch: WebSearchConf,
     pub css: String,
 }
 
+fn parse_bool(val: &str) -> bool {
+    matches!(val, "true" | "yes" | "1")
+}
+
+fn parse_list(val: &str) -> Vec<String> {
+    val.split([' ', ','])
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect()
+}
+
 impl Config {
-    pub fn get_conf(conf_path: &PathBuf) -> GeneralConf {
-        let mut general = GeneralConf::default();
+    pub fn get_conf(conf_path: &Path) -> Self {
+        let mut conf = Self::default();
         if let Ok(mut f) = std::fs::File::open(conf_path) {
             let mut data = String::new();
             let _ = f.read_to_string(&mut data);
-            let mut is_in_general = false;
+            let mut section = String::new();
 
             for (line, item) in ini_roundtrip::Parser::new(&data).enumerate() {
                 match item {
                     ini_roundtrip::Item::Error(e) => {
                         warn!("{}:{line}: {e}", conf_path.display());
                     }
-                    ini_roundtrip::Item::Section {
-                        name: "general", ..
-                    } => {
-                        is_in_general = true;
+                    ini_roundtrip::Item::Section { name, .. } => {
+                        section = name.to_string();
                     }
                     ini_roundtrip::Item::Property {
-                        key: "theme", val, ..
-                    } => {
-                        if is_in_general && val.is_some() {
-                            general.theme = val.unwrap().trim().to_string();
-                        }
-                    }
-                    ini_roundtrip::Item::Property {
-                        key: "terminal",
-                        val,
-                        ..
-                    } => {
-                        if is_in_general && val.is_some() {
-                            general.terminal = val.unwrap().trim().to_string();
-                        }
-                    }
-                    ini_roundtrip::Item::Property {
-                        key: "args", val, ..
-                    } => {
-                        if is_in_general && val.is_some() {
-                            general.args = val
-                                .unwrap()
-                                .trim()
-                                .split(' ')
-                                .map(|x| x.to_string())
-                                .collect();
-                        }
-                    }
-                    ini_roundtrip::Item::Property {
-                        key: "search_placeholder",
-                        val,
+                        key,
+                        val: Some(val),
                         ..
                     } => {
-                        if is_in_general && val.is_some() {
-                            general.search_placeholder = val.unwrap().to_string();
+                        let known = conf.set(&section, key, val);
+                        if !known {
+                            warn!(
+                                "{}:{line}: unknown key `{key}` in [{section}]",
+                                conf_path.display()
+                            );
                         }
                     }
                     _ => {}
                 }
             }
         }
-        return general;
+
+        conf
+    }
+
+    /// Applies a single `key = val` found under `[section]`, returns `false`
+    /// if the key is not known.
+    fn set(&mut self, section: &str, key: &str, val: &str) -> bool {
+        match (section, key) {
+            ("general", "theme") => self.general.theme = val.to_string(),
+            ("general", "terminal") => self.general.terminal = val.to_string(),
+            ("general", "args") => {
+                self.general.args = val.split(' ').map(|x| x.to_string()).collect();
+            }
+            ("general", "search_placeholder") => {
+                self.general.search_placeholder = val.to_string();
+            }
+            ("apps", "empty_state") => match EmptyState::parse(val) {
+                Some(empty_state) => self.apps.empty_state = empty_state,
+                None => warn!("invalid empty_state `{val}`"),
+            },
+            ("apps", "pinned") => self.apps.pinned = parse_list(val),
+            ("apps", "hidden") => self.apps.hidden = parse_list(val),
+            ("calc", "precision") => match val.parse() {
+                Ok(precision) => self.calc.precision = precision,
+                Err(_) => warn!("invalid precision `{val}`"),
+            },
+            ("calc", "thousands_separator") => {
+                // values are trimmed, spaces need a name
+                self.calc.thousands_separator = match val {
+                    "none" => String::new(),
+                    "space" => " ".to_string(),
+                    "thin_space" => "\u{202f}".to_string(),
+                    _ => val.to_string(),
+                };
+            }
+            ("calc", "scientific_above") => match val.parse() {
+                Ok(threshold) => self.calc.scientific_above = threshold,
+                Err(_) => warn!("invalid scientific_above `{val}`"),
+            },
+            ("calc", "scientific_below") => match val.parse() {
+                Ok(threshold) => self.calc.scientific_below = threshold,
+                Err(_) => warn!("invalid scientific_below `{val}`"),
+            },
+            ("calc", "copy_to_primary") => self.calc.copy_to_primary = parse_bool(val),
+            ("calc.define", key) => match calc::Definition::parse(key, val) {
+                Some(def) => self.calc.definitions.push(def),
+                None => return false,
+            },
+            ("files", "enabled") => self.files.enabled = parse_bool(val),
+            ("files", "roots") => self.files.roots = parse_list(val),
+            ("files", "ignore") => self.files.ignore = parse_list(val),
+            ("files", "hidden") => self.files.hidden = parse_bool(val),
+            ("files", "gitignore") => self.files.gitignore = parse_bool(val),
+            ("locate", "enabled") => self.locate.enabled = parse_bool(val),
+            ("locate", "databases") => self.locate.databases = parse_list(val),
+            ("recent", "enabled") => self.recent.enabled = parse_bool(val),
+            ("executables", "enabled") => self.executables.enabled = parse_bool(val),
+            ("executables", "terminal") => self.executables.terminal = parse_list(val),
+            ("browsers", "enabled") => self.browsers.enabled = parse_bool(val),
+            ("browsers", "history") => self.browsers.history = parse_bool(val),
+            ("browsers", "profiles") => self.browsers.profiles = parse_list(val),
+            ("ssh", "enabled") => self.ssh.enabled = parse_bool(val),
+            ("windows", "enabled") => self.windows.enabled = parse_bool(val),
+            ("websearch", "fallback") => {
+                self.websearch.fallback = (val != "none").then(|| val.to_string());
+            }
+            // any other key of [websearch] declares an engine
+            ("websearch", key) => match websearch::Engine::parse(key, val) {
+                Some(engine) => self.websearch.add(engine),
+                None => return false,
+            },
+            (section, key) if section.starts_with("alias.") => {
+                return self.set_alias(&section["alias.".len()..], key, val);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn set_alias(&mut self, alias: &str, key: &str, val: &str) -> bool {
+        let conf = match self.aliases.iter_mut().position(|a| a.alias == alias) {
+            Some(i) => &mut self.aliases[i],
+            None => {
+                self.aliases.push(AliasConf {
+                    alias: alias.to_string(),
+                    ..Default::default()
+                });
+                self.aliases.last_mut().unwrap()
+            }
+        };
+
+        match key {
+            "exec" => conf.exec = Some(val.to_string()),
+            "app" => conf.app = Some(val.strip_suffix(".desktop").unwrap_or(val).to_string()),
+            "name" => conf.name = Some(val.to_string()),
+            "icon" => conf.icon = Some(val.to_string()),
+            "description" => conf.description = Some(val.to_string()),
+            "terminal" => conf.terminal = parse_bool(val),
+            _ => return false,
+        }
+
+        true
     }
 
     pub fn parse(path: std::path::PathBuf) -> Self {
@@ -111,13 +409,109 @@ impl Config {
             }
         }
 
-        return Self {
-            general: Self::get_conf(&path),
+        Self {
             css,
-        };
+            ..Self::get_conf(&path)
+        }
     }
 }
 
+/// Adds `value` to (or removes it from) the list stored under `key` in
+/// `[section]`, every other line of the file is kept as is. Returns whether
+/// the file changed.
+pub fn edit_list(
+    conf_path: &Path,
+    section: &str,
+    key: &str,
+    value: &str,
+    add: bool,
+) -> std::io::Result<bool> {
+    let data = match std::fs::read_to_string(conf_path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let mut lines: Vec<String> = vec![];
+    let mut current = "";
+    let mut section_line = None;
+    let mut found = false;
+    let mut changed = false;
+
+    for item in ini_roundtrip::Parser::new(&data) {
+        match item {
+            ini_roundtrip::Item::Section { name, raw } => {
+                current = name;
+                lines.push(raw.to_string());
+                if name == section && section_line.is_none() {
+                    section_line = Some(lines.len() - 1);
+                }
+            }
+            ini_roundtrip::Item::Property { key: k, val, .. }
+                if current == section && k == key && !found =>
+            {
+                found = true;
+                let mut list = val.map(parse_list).unwrap_or_default();
+                let position = list.iter().position(|x| x == value);
+                match (add, position) {
+                    (true, None) => list.push(value.to_string()),
+                    (false, Some(i)) => {
+                        list.remove(i);
+                    }
+                    _ => {}
+                }
+                changed = add != position.is_some();
+                lines.push(format!("{key} = {}", list.join(" ")));
+            }
+            ini_roundtrip::Item::Error(raw)
+            | ini_roundtrip::Item::Property { raw, .. }
+            | ini_roundtrip::Item::Comment { raw }
+            | ini_roundtrip::Item::Blank { raw } => lines.push(raw.to_string()),
+            ini_roundtrip::Item::SectionEnd => {}
+        }
+    }
+
+    if !found && add {
+        let line = format!("{key} = {value}");
+        match section_line {
+            Some(i) => lines.insert(i + 1, line),
+            None => {
+                lines.push(String::new());
+                lines.push(format!("[{section}]"));
+                lines.push(line);
+            }
+        }
+        changed = true;
+    }
+
+    if changed {
+        replace_file(conf_path, &format!("{}\n", lines.join("\n")))?;
+    }
+
+    Ok(changed)
+}
+
+/// Writes `data` next to `path` first and renames it over, a crash never
+/// leaves half a config. A symlinked config keeps its link, the file it
+/// points to is replaced.
+fn replace_file(path: &Path, data: &str) -> std::io::Result<()> {
+    let target = match std::fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e),
+    };
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let tmp = target.with_file_name(name);
+
+    let mut f = std::fs::File::create(&tmp)?;
+    f.write_all(data.as_bytes())?;
+    if let Ok(meta) = std::fs::metadata(&target) {
+        f.set_permissions(meta.permissions())?;
+    }
+    std::fs::rename(&tmp, &target)
+}
+
 pub fn init_config_dir() -> std::path::PathBuf {
     let raw_path = std::env::var("XDG_CONFIG_HOME")
         .unwrap_or(format!("{}/.config", std::env::var("HOME").unwrap()));
@@ -135,11 +529,110 @@ pub fn init_config_dir() -> std::path::PathBuf {
         if let Ok(mut f) = std::fs::File::create(&config_file) {
             let _ = f.write(
                 DEFAULT_CONFIG
-                    .replace("%PLACEHOLDER%", &t!("search_placeholder").to_string())
+                    .replace("%PLACEHOLDER%", &t!("search_placeholder"))
                     .as_bytes(),
             );
         }
     }
 
-    return config_file;
+    config_file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_list_keeps_the_rest_of_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("default.conf");
+        let original = "\
+# seekr config
+[general]
+term = foot ; inline comment
+
+[apps]
+# pinned first
+pinned = firefox
+hidden = htop
+empty_state = pinned
+
+[calc]
+precision = 4
+";
+        std::fs::write(&path, original).unwrap();
+
+        assert!(edit_list(&path, "apps", "pinned", "foot", true).unwrap());
+        assert!(!edit_list(&path, "apps", "pinned", "foot", true).unwrap());
+        assert!(edit_list(&path, "apps", "hidden", "htop", false).unwrap());
+        assert!(edit_list(&path, "apps", "hidden", "btop", true).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            original
+                .replace("pinned = firefox", "pinned = firefox foot")
+                .replace("hidden = htop", "hidden = btop")
+        );
+
+        assert!(edit_list(&path, "apps", "pinned", "foot", false).unwrap());
+        assert!(edit_list(&path, "apps", "hidden", "btop", false).unwrap());
+        assert!(edit_list(&path, "apps", "hidden", "htop", true).unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn calc_definitions() {
+        let mut conf = Config::default();
+        assert!(conf.set("calc.define", "g", "9.81"));
+        assert!(conf.set("calc.define", "hyp(a, b)", "sqrt(a^2 + b^2)"));
+        assert!(!conf.set("calc.define", "2x", "3"));
+        // a typo is not a definition
+        assert!(!conf.set("calc", "precison", "3"));
+        assert!(conf.set("calc", "precision", "3"));
+
+        let names: Vec<&str> = conf
+            .calc
+            .definitions
+            .iter()
+            .map(|def| def.name.as_str())
+            .collect();
+        assert_eq!(names, ["g", "hyp"]);
+    }
+
+    #[test]
+    fn edit_list_replaces_symlink_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let dotfiles = dir.path().join("dotfiles.conf");
+        let path = dir.path().join("default.conf");
+        std::fs::write(&dotfiles, "[apps]\n").unwrap();
+        std::os::unix::fs::symlink(&dotfiles, &path).unwrap();
+
+        assert!(edit_list(&path, "apps", "pinned", "firefox", true).unwrap());
+        assert!(std::fs::symlink_metadata(&path)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(&dotfiles).unwrap(),
+            "[apps]\npinned = firefox\n"
+        );
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn edit_list_adds_missing_keys_and_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("default.conf");
+        std::fs::write(&path, "[apps]\nempty_state = all\n").unwrap();
+
+        assert!(edit_list(&path, "apps", "pinned", "firefox", true).unwrap());
+        assert!(edit_list(&path, "files", "roots", "~/src", true).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[apps]\npinned = firefox\nempty_state = all\n\n[files]\nroots = ~/src\n"
+        );
+    }
 }
@@ -0,0 +1,392 @@
+use crate::calc::{format_number, Answer};
+use crate::conf::CalcConf;
+use crate::tz::{self, Zone, SECONDS_PER_DAY};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// about 142 million years either way, the calendar and zone math on such
+// instants can't overflow
+const MAX_SECONDS: i64 = 1 << 52;
+
+/// A step of date arithmetic, `3 weeks` is `Days(21)`.
+#[derive(Clone, Copy)]
+enum Step {
+    Seconds(i64),
+    /// keeps the wall clock time across DST changes
+    Days(i64),
+    /// clamped to the end of the month, Jan 31 + 1 month is Feb 28
+    Months(i64),
+}
+
+fn parse_unit(word: &str) -> Option<Step> {
+    let step = match word {
+        "s" | "sec" | "secs" | "second" | "seconds" => Step::Seconds(1),
+        "min" | "mins" | "minute" | "minutes" => Step::Seconds(60),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Step::Seconds(3600),
+        "d" | "day" | "days" => Step::Days(1),
+        "w" | "wk" | "wks" | "week" | "weeks" => Step::Days(7),
+        "mo" | "month" | "months" => Step::Months(1),
+        "y" | "yr" | "yrs" | "year" | "years" => Step::Months(12),
+        _ => return None,
+    };
+    Some(step)
+}
+
+/// An instant along with the zone it is displayed in.
+struct Moment {
+    t: i64,
+    zone: Zone,
+    /// `today + 3 days` is a date, `now + 3 days` a date and a time
+    has_time: bool,
+}
+
+impl Moment {
+    fn local(&self) -> i64 {
+        self.t + self.zone.offset_at(self.t).0
+    }
+
+    /// `None` when the result is out of range.
+    fn shift(&mut self, step: Step, n: i64) -> Option<()> {
+        let t = match step {
+            Step::Seconds(unit) => {
+                self.has_time = true;
+                self.t.checked_add(n.checked_mul(unit)?)?
+            }
+            Step::Days(unit) => {
+                let days = n.checked_mul(unit)?.checked_mul(SECONDS_PER_DAY)?;
+                self.zone.to_utc(in_range(self.local().checked_add(days)?)?)
+            }
+            Step::Months(unit) => {
+                let local = self.local();
+                let (year, month, day) = tz::civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+                let months = (year * 12 + month as i64 - 1).checked_add(n.checked_mul(unit)?)?;
+                let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+                if year.abs() > MAX_SECONDS / (366 * SECONDS_PER_DAY) {
+                    return None;
+                }
+                let day = day.min(tz::days_in_month(year, month));
+                self.zone.to_utc(
+                    tz::days_from_civil(year, month, day) * SECONDS_PER_DAY
+                        + local.rem_euclid(SECONDS_PER_DAY),
+                )
+            }
+        };
+        self.t = in_range(t)?;
+        Some(())
+    }
+
+    fn display(&self) -> String {
+        let (offset, abbr) = self.zone.offset_at(self.t);
+        let local = self.t + offset;
+        let (year, month, day) = tz::civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+        let date = format!("{year:04}-{month:02}-{day:02}");
+        if !self.has_time {
+            return date;
+        }
+
+        let seconds = local.rem_euclid(SECONDS_PER_DAY);
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        if seconds == 0 {
+            format!("{date} {hours:02}:{minutes:02} {abbr}")
+        } else {
+            format!("{date} {hours:02}:{minutes:02}:{seconds:02} {abbr}")
+        }
+    }
+}
+
+fn in_range(t: i64) -> Option<i64> {
+    (-MAX_SECONDS..=MAX_SECONDS).contains(&t).then_some(t)
+}
+
+fn parse_date(token: &str) -> Option<i64> {
+    let mut parts = token.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (year, month, day): (i64, u32, u32) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month) || day == 0 || day > tz::days_in_month(year, month) {
+        return None;
+    }
+    Some(tz::days_from_civil(year, month, day))
+}
+
+/// `HH:MM[:SS]` in seconds past midnight.
+fn parse_time(token: &str) -> Option<i64> {
+    let mut seconds = 0;
+    let mut parts = 0;
+    for (i, part) in token.split(':').enumerate() {
+        let value: i64 = part.parse().ok()?;
+        let max = if i == 0 { 23 } else { 59 };
+        if i > 2 || part.len() > 2 || value > max {
+            return None;
+        }
+        seconds += value * [3600, 60, 1][i];
+        parts += 1;
+    }
+    (parts >= 2).then_some(seconds)
+}
+
+/// Parses `<base> [zone] [(+|-) <n> <unit>]...`, the base being `now`,
+/// `today`, `tomorrow`, `yesterday`, a `YYYY-MM-DD` date and/or a `HH:MM`
+/// time.
+fn parse_moment(text: &str, now: i64, local: &Zone) -> Option<Moment> {
+    // `now+3 days` and `now-3d` as separate tokens, dates keep their dashes
+    // until they are whole
+    let mut spaced = String::with_capacity(text.len());
+    for c in text.chars() {
+        let token = spaced.rsplit(' ').next().unwrap_or_default();
+        let ends_token = token.is_empty()
+            || matches!(token, "now" | "today" | "tomorrow" | "yesterday")
+            || parse_date(token).is_some()
+            || parse_time(token).is_some()
+            || parse_unit(token.trim_start_matches(|c: char| c.is_ascii_digit())).is_some();
+        match c {
+            '+' => spaced.push_str(" + "),
+            '-' if ends_token => spaced.push_str(" - "),
+            _ => spaced.push(c),
+        }
+    }
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+
+    // the day, `None` standing for today in the zone, and a shift in days,
+    // `None` altogether for now
+    let mut i = 1;
+    let wall: Option<(Option<i64>, i64)> = match *tokens.first()? {
+        "now" => None,
+        "today" => Some((None, 0)),
+        "tomorrow" => Some((None, 1)),
+        "yesterday" => Some((None, -1)),
+        token if parse_date(token).is_some() => Some((parse_date(token), 0)),
+        // a time alone is today at that time
+        token if parse_time(token).is_some() => {
+            i = 0;
+            Some((None, 0))
+        }
+        _ => return None,
+    };
+
+    let time = match tokens.get(i).and_then(|token| parse_time(token)) {
+        Some(time) if wall.is_some() => {
+            i += 1;
+            Some(time)
+        }
+        _ => None,
+    };
+
+    let zone_end = tokens[i..]
+        .iter()
+        .position(|token| matches!(*token, "+" | "-"))
+        .map_or(tokens.len(), |end| i + end);
+    let zone = match tokens[i..zone_end].join(" ") {
+        name if name.is_empty() => local.clone(),
+        name => Zone::find(&name)?,
+    };
+    i = zone_end;
+
+    let mut moment = match wall {
+        None => Moment {
+            t: now,
+            zone,
+            has_time: true,
+        },
+        Some((days, shift)) => {
+            let today = (now + zone.offset_at(now).0).div_euclid(SECONDS_PER_DAY);
+            let days = days.unwrap_or(today) + shift;
+            Moment {
+                t: zone.to_utc(days * SECONDS_PER_DAY + time.unwrap_or(0)),
+                zone,
+                has_time: time.is_some(),
+            }
+        }
+    };
+
+    while i < tokens.len() {
+        let sign = match tokens[i] {
+            "+" => 1,
+            "-" => -1,
+            _ => return None,
+        };
+        // `3 weeks` or `3weeks`
+        let token = tokens.get(i + 1)?;
+        let digits = token
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(token.len());
+        let n: i64 = token[..digits].parse().ok()?;
+        let (unit, next) = match &token[digits..] {
+            "" => (*tokens.get(i + 2)?, i + 3),
+            unit => (unit, i + 2),
+        };
+        moment.shift(parse_unit(unit)?, sign * n)?;
+        i = next;
+    }
+
+    Some(moment)
+}
+
+/// Answers `<unit> until|since <date>`, the unit as typed is kept.
+fn count_until(
+    query: &str,
+    now: i64,
+    local: &Zone,
+    conf: &CalcConf,
+) -> Option<(String, String, f64)> {
+    let (unit, target, until) = match query.split_once(" until ") {
+        Some((unit, target)) => (unit, target, true),
+        None => {
+            let (unit, target) = query.split_once(" since ")?;
+            (unit, target, false)
+        }
+    };
+
+    let target = parse_moment(target, now, local)?;
+    let count = match parse_unit(unit)? {
+        // whole days between the dates, whatever time it is now
+        Step::Days(unit) if !target.has_time => {
+            let today = (now + target.zone.offset_at(now).0).div_euclid(SECONDS_PER_DAY);
+            let days = target.local().div_euclid(SECONDS_PER_DAY) - today;
+            days as f64 / unit as f64
+        }
+        Step::Days(unit) => (target.t - now) as f64 / (unit * SECONDS_PER_DAY) as f64,
+        Step::Seconds(unit) => (target.t - now) as f64 / unit as f64,
+        Step::Months(_) => return None,
+    };
+    let count = if until { count } else { -count };
+
+    let plain = format_number(
+        count,
+        &CalcConf {
+            thousands_separator: String::new(),
+            ..conf.clone()
+        },
+    );
+    Some((
+        format!("{} {unit}", format_number(count, conf)),
+        plain,
+        count,
+    ))
+}
+
+/// Answers date queries: `now + 3 weeks`, `days until 2027-01-01`,
+/// `1700000000 unix`, `2027-01-01 unix` and `14:00 UTC in Tokyo`. `local`
+/// is the zone of the system, see `Zone::local`.
+pub fn evaluate(query: &str, local: &Zone, conf: &CalcConf) -> Option<Answer> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+    evaluate_at(query, now, local, conf)
+}
+
+fn evaluate_at(query: &str, now: i64, local: &Zone, conf: &CalcConf) -> Option<Answer> {
+    let expr = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    let query = expr.to_lowercase();
+
+    let (value, plain, result) = if let Some(count) = count_until(&query, now, local, conf) {
+        count
+    } else if let Some(head) = query.strip_suffix(" unix") {
+        let head = head
+            .strip_suffix(" in")
+            .or_else(|| head.strip_suffix(" to"))
+            .unwrap_or(head);
+        match head.parse::<i64>() {
+            Ok(t) => {
+                let t = in_range(t)?;
+                let moment = Moment {
+                    t,
+                    zone: local.clone(),
+                    has_time: true,
+                };
+                (moment.display(), moment.display(), t as f64)
+            }
+            Err(_) => {
+                let t = parse_moment(head, now, local)?.t;
+                (t.to_string(), t.to_string(), t as f64)
+            }
+        }
+    } else {
+        let converted = query.rsplit_once(" in ").and_then(|(moment, zone)| {
+            let mut moment = parse_moment(moment, now, local)?;
+            moment.zone = Zone::find(zone)?;
+            Some(moment)
+        });
+        let moment = match converted {
+            Some(moment) => moment,
+            None => parse_moment(&query, now, local)?,
+        };
+        (moment.display(), moment.display(), moment.t as f64)
+    };
+
+    Some(Answer {
+        expr,
+        value,
+        plain,
+        result,
+        assignments: vec![],
+        bases: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2026-10-19 14:30 UTC, 10:30 in New York
+    const NOW: i64 = 1792420200;
+
+    fn value(query: &str) -> Option<String> {
+        let new_york = Zone::from_rule("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        evaluate_at(query, NOW, &new_york, &CalcConf::default()).map(|answer| answer.value)
+    }
+
+    #[test]
+    fn shifts() {
+        // the wall clock time is kept across the end of DST
+        assert_eq!(value("now + 3 weeks").unwrap(), "2026-11-09 10:30 EST");
+        assert_eq!(value("now+2h").unwrap(), "2026-10-19 12:30 EDT");
+        assert_eq!(value("now-3d").unwrap(), "2026-10-16 10:30 EDT");
+        assert_eq!(value("today+1d-1d").unwrap(), "2026-10-19");
+        assert_eq!(value("tomorrow-1 week").unwrap(), "2026-10-13");
+        assert_eq!(value("2027-01-01-3d").unwrap(), "2026-12-29");
+        assert_eq!(value("12:00-90min").unwrap(), "2026-10-19 10:30 EDT");
+    }
+
+    #[test]
+    fn month_end() {
+        assert_eq!(value("2024-01-31 + 1 month").unwrap(), "2024-02-29");
+        assert_eq!(value("2023-01-31 + 1 month").unwrap(), "2023-02-28");
+        assert_eq!(value("2024-02-29 + 1 year").unwrap(), "2025-02-28");
+        assert_eq!(value("2024-03-31 - 1mo").unwrap(), "2024-02-29");
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(value("days until 2027-01-01").unwrap(), "74 days");
+        assert_eq!(value("days since 2026-10-01").unwrap(), "18 days");
+        assert_eq!(value("weeks since 2026-10-05").unwrap(), "2 weeks");
+        assert_eq!(value("hours until tomorrow").unwrap(), "13.5 hours");
+        assert_eq!(value("months until 2027-01-01"), None);
+    }
+
+    #[test]
+    fn unix_time() {
+        assert_eq!(value("1700000000 unix").unwrap(), "2023-11-14 17:13:20 EST");
+        assert_eq!(value("2027-01-01 unix").unwrap(), "1798779600");
+        assert_eq!(value("2027-01-01 utc to unix").unwrap(), "1798761600");
+    }
+
+    #[test]
+    fn zones() {
+        assert_eq!(value("14:00 UTC in Tokyo").unwrap(), "2026-10-19 23:00 JST");
+        assert_eq!(value("now in utc").unwrap(), "2026-10-19 14:30 UTC");
+        assert_eq!(value("now in nowhere"), None);
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(value("now + 99999999999999999 days"), None);
+        assert_eq!(value("now - 99999999999999999 weeks"), None);
+        assert_eq!(value("now + 9223372036854775807 s"), None);
+        assert_eq!(value("2024-01-31 + 999999999999999999 months"), None);
+        assert_eq!(value("now + 99999999999999999999 days"), None);
+        assert_eq!(value("9223372036854775807 unix"), None);
+        assert_eq!(value("-9223372036854775808 unix"), None);
+    }
+}
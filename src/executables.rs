@@ -0,0 +1,195 @@
+use crate::app::AppEntry;
+use crate::shell::{Origin, ShellCommand};
+use gtk::glib;
+use std::collections::{BTreeMap, HashSet};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::debug;
+
+/// An executable found in `$PATH`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Executable {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl Executable {
+    /// Runs the executable without arguments, in `term` or detached.
+    pub fn launch(&self, in_terminal: bool, term: &str, term_launch_args: &[String]) {
+        let command = ShellCommand {
+            command: glib::shell_quote(&self.path).to_string_lossy().to_string(),
+            origin: Origin::Path,
+        };
+        command.run(in_terminal, term, term_launch_args);
+    }
+}
+
+/// The executables of `$PATH`, scanned again only when `$PATH` or the
+/// mtime of one of its directories changes.
+#[derive(Default)]
+pub struct ExecutableIndex {
+    dirs: Vec<(PathBuf, Option<SystemTime>)>,
+    entries: Arc<Vec<Executable>>,
+}
+
+impl ExecutableIndex {
+    pub fn refresh(&mut self) -> Arc<Vec<Executable>> {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let dirs: Vec<(PathBuf, Option<SystemTime>)> = std::env::split_paths(&path)
+            .map(|dir| {
+                let mtime = std::fs::metadata(&dir).and_then(|meta| meta.modified());
+                (dir, mtime.ok())
+            })
+            .collect();
+        if dirs == self.dirs {
+            return self.entries.clone();
+        }
+
+        let entries = scan(&dirs);
+        debug!("found {} executables in $PATH", entries.len());
+        self.dirs = dirs;
+        self.entries = Arc::new(entries);
+        self.entries.clone()
+    }
+}
+
+/// Executables sorted by name, the first directory of `$PATH` wins.
+fn scan(dirs: &[(PathBuf, Option<SystemTime>)]) -> Vec<Executable> {
+    let mut found = BTreeMap::new();
+    for (dir, _) in dirs {
+        let Ok(read_dir) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in read_dir.filter_map(|e| e.ok()) {
+            // follows symlinks, most of `/usr/bin` is made of them
+            let path = entry.path();
+            let executable = path.metadata().is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            });
+            if executable {
+                let name = entry.file_name().to_string_lossy().to_string();
+                found.entry(name).or_insert(path);
+            }
+        }
+    }
+    found
+        .into_iter()
+        .map(|(name, path)| Executable { name, path })
+        .collect()
+}
+
+/// The program an `Exec` line runs, `env FOO=1 /usr/bin/foo %U` runs `foo`
+/// and so do `sh -c 'foo %U'` and `flatpak run --command=foo org.foo.Foo`.
+fn program(exec: &str) -> Option<String> {
+    let argv: Vec<String> = glib::shell_parse_argv(exec)
+        .ok()?
+        .into_iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let mut args = argv.as_slice();
+    loop {
+        let (first, rest) = args.split_first()?;
+        let name = first.rsplit('/').next().unwrap_or(first);
+        match (name, rest.first().map(String::as_str)) {
+            ("env", _) => {
+                args = rest;
+                while let Some((arg, rest)) = args.split_first() {
+                    args = match arg.as_str() {
+                        // options taking a value
+                        "-u" | "--unset" | "-C" | "--chdir" => rest.get(1..)?,
+                        _ if arg.starts_with('-') || arg.contains('=') => rest,
+                        _ => break,
+                    };
+                }
+            }
+            ("sh" | "bash" | "dash" | "zsh", Some("-c")) => return program(rest.get(1)?),
+            ("flatpak", Some("run")) => {
+                let mut options = rest[1..].iter();
+                return options.find_map(|arg| {
+                    if let Some(command) = arg.strip_prefix("--command=") {
+                        return Some(command.rsplit('/').next()?.to_string());
+                    }
+                    // the app id, the command is the one it exports
+                    (!arg.starts_with('-')).then(|| arg.clone())
+                });
+            }
+            _ if first.contains('=') => args = rest,
+            _ => return Some(name.to_string()),
+        }
+    }
+}
+
+/// Names of the programs started by listed desktop entries, their
+/// executables already show up as apps.
+pub fn app_programs(entries: &[AppEntry]) -> HashSet<String> {
+    entries
+        .iter()
+        .filter(|entry| !entry.no_display)
+        .filter_map(|entry| program(&entry.exec))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(exec: &str) -> AppEntry {
+        AppEntry {
+            id: "org.example.App".to_string(),
+            exec: exec.to_string(),
+            need_terminal: false,
+            icon: String::new(),
+            name: "App".to_string(),
+            description: String::new(),
+            path: PathBuf::from("/usr/share/applications/org.example.App.desktop"),
+            mime_types: vec![],
+            aliases: vec![],
+            no_display: false,
+        }
+    }
+
+    #[test]
+    fn exec_program() {
+        assert_eq!(program("firefox %u").as_deref(), Some("firefox"));
+        assert_eq!(program("\"/opt/My App/app\" %F").as_deref(), Some("app"));
+        assert_eq!(
+            program("env -u X FOO=1 /usr/bin/foo %U").as_deref(),
+            Some("foo")
+        );
+        assert_eq!(program("sh -c 'BAR=2 bar --new'").as_deref(), Some("bar"));
+        assert_eq!(
+            program("/bin/bash -c \"baz --new\"").as_deref(),
+            Some("baz")
+        );
+        assert_eq!(
+            program("flatpak run --branch=stable org.gimp.GIMP %U").as_deref(),
+            Some("org.gimp.GIMP")
+        );
+        assert_eq!(
+            program("flatpak run --command=gimp-2.10 org.gimp.GIMP").as_deref(),
+            Some("gimp-2.10")
+        );
+        assert_eq!(program("'unterminated").as_deref(), None);
+        assert_eq!(program("env").as_deref(), None);
+    }
+
+    #[test]
+    fn programs_of_entries() {
+        let entries = [
+            entry("env GDK_BACKEND=x11 gimp %U"),
+            entry("sh -c 'htop'"),
+            entry("flatpak run org.example.App"),
+            entry("\""),
+            AppEntry {
+                no_display: true,
+                ..entry("vim %F")
+            },
+        ];
+        let programs = app_programs(&entries);
+        let mut programs: Vec<_> = programs.iter().map(String::as_str).collect();
+        programs.sort();
+        assert_eq!(programs, ["gimp", "htop", "org.example.App"]);
+    }
+}
@@ -0,0 +1,493 @@
+use crate::conf::FilesConf;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use gtk::gio::{self, prelude::*};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+/// A file or directory found under one of the indexed roots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub is_dir: bool,
+}
+
+impl FileEntry {
+    /// Opens the file with the default handler of its type.
+    pub fn open(&self) {
+        let uri = self.uri();
+        if let Err(e) = gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>) {
+            warn!("unable to open {}: {e}", self.path.display());
+        }
+    }
+
+    pub fn uri(&self) -> String {
+        gio::File::for_path(&self.path).uri().to_string()
+    }
+
+    /// The MIME type guessed from the file name.
+    pub fn mime_type(&self) -> String {
+        if self.is_dir {
+            return "inode/directory".to_string();
+        }
+        let (content_type, _) = gio::content_type_guess(Some(&self.path), &[]);
+        gio::content_type_get_mime_type(&content_type)
+            .map_or("application/octet-stream".to_string(), |mime_type| {
+                mime_type.to_string()
+            })
+    }
+
+    /// The parent directory, with `~` standing for the home directory.
+    pub fn location(&self) -> String {
+        abbreviate_home(self.path.parent().unwrap_or(Path::new("/")))
+    }
+}
+
+/// `path` with `~` standing for the home directory.
+pub fn abbreviate_home(path: &Path) -> String {
+    match std::env::var("HOME") {
+        Ok(home) if !home.is_empty() => match path.strip_prefix(&home) {
+            Ok(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+            Ok(rest) => format!("~/{}", rest.display()),
+            Err(_) => path.display().to_string(),
+        },
+        _ => path.display().to_string(),
+    }
+}
+
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) => PathBuf::from(format!(
+            "{}{rest}",
+            std::env::var("HOME").unwrap_or_default()
+        )),
+        None => PathBuf::from(path),
+    }
+}
+
+/// A directory as seen by the last walk, reused as long as neither its
+/// mtime nor the one of its `.gitignore` changed. A changed `.gitignore`
+/// filters the whole subtree differently, it gets read again.
+struct DirNode {
+    mtime: SystemTime,
+    /// of the `.gitignore` read, `None` without one
+    gitignore_mtime: Option<SystemTime>,
+    files: Vec<String>,
+    subdirs: Vec<String>,
+    gitignore: Option<Arc<Gitignore>>,
+}
+
+/// Filename index of the `[files]` roots, refreshed incrementally: only
+/// the directories whose mtime changed since the last walk are read again.
+pub struct FileIndex {
+    conf: FilesConf,
+    ignore: GlobSet,
+    dirs: HashMap<PathBuf, DirNode>,
+}
+
+impl FileIndex {
+    pub fn new(conf: &FilesConf) -> Self {
+        let mut ignore = GlobSetBuilder::new();
+        for pattern in &conf.ignore {
+            match Glob::new(pattern) {
+                Ok(glob) => {
+                    ignore.add(glob);
+                }
+                Err(e) => warn!("invalid ignore pattern `{pattern}`: {e}"),
+            }
+        }
+
+        Self {
+            conf: conf.clone(),
+            ignore: ignore.build().unwrap_or_else(|_| GlobSet::empty()),
+            dirs: HashMap::new(),
+        }
+    }
+
+    pub fn conf(&self) -> &FilesConf {
+        &self.conf
+    }
+
+    /// Walks the roots again and returns every indexed entry.
+    pub fn refresh(&mut self) -> Vec<FileEntry> {
+        let roots = if self.conf.enabled {
+            self.conf.roots.clone()
+        } else {
+            vec![]
+        };
+        let mut walk = Walk {
+            index: self,
+            seen: HashMap::new(),
+            rescanned: 0,
+            entries: vec![],
+        };
+        for root in roots {
+            walk.visit(&expand_home(&root), &mut vec![], false);
+        }
+
+        let Walk {
+            seen,
+            rescanned,
+            entries,
+            ..
+        } = walk;
+        debug!(
+            "indexed {} files, {rescanned} of {} directories rescanned",
+            entries.len(),
+            seen.len()
+        );
+        self.dirs = seen;
+        entries
+    }
+
+    fn is_ignored(
+        &self,
+        path: &Path,
+        name: &str,
+        is_dir: bool,
+        gitignores: &[Arc<Gitignore>],
+    ) -> bool {
+        if name == ".git" || (!self.conf.hidden && name.starts_with('.')) {
+            return true;
+        }
+        if self.ignore.is_match(name) || self.ignore.is_match(path) {
+            return true;
+        }
+
+        // the closest .gitignore deciding something wins
+        for gitignore in gitignores.iter().rev() {
+            let matched = gitignore.matched(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        false
+    }
+}
+
+fn read_gitignore(dir: &Path) -> Option<Gitignore> {
+    let path = dir.join(".gitignore");
+    if !path.is_file() {
+        return None;
+    }
+
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(&path) {
+        warn!("{}: {e}", path.display());
+    }
+    builder.build().ok()
+}
+
+struct Walk<'a> {
+    index: &'a mut FileIndex,
+    seen: HashMap<PathBuf, DirNode>,
+    rescanned: usize,
+    entries: Vec<FileEntry>,
+}
+
+impl Walk<'_> {
+    /// Lists `dir` and its subdirectories, all of them read again when
+    /// `stale`.
+    fn visit(&mut self, dir: &Path, gitignores: &mut Vec<Arc<Gitignore>>, stale: bool) {
+        // roots may be listed twice or nested
+        if self.seen.contains_key(dir) {
+            return;
+        }
+        let Ok(mtime) = std::fs::symlink_metadata(dir).and_then(|meta| meta.modified()) else {
+            return;
+        };
+        let gitignore_mtime = match self.index.conf.gitignore {
+            true => std::fs::metadata(dir.join(".gitignore"))
+                .and_then(|meta| meta.modified())
+                .ok(),
+            false => None,
+        };
+
+        let previous = self.index.dirs.remove(dir);
+        let stale = stale
+            || previous
+                .as_ref()
+                .is_some_and(|node| node.gitignore_mtime != gitignore_mtime);
+        let node = match previous {
+            Some(node) if !stale && node.mtime == mtime => node,
+            _ => {
+                self.rescanned += 1;
+                DirNode {
+                    gitignore_mtime,
+                    ..self.scan(dir, mtime, gitignores)
+                }
+            }
+        };
+
+        for name in &node.files {
+            self.entries.push(FileEntry {
+                path: dir.join(name),
+                name: name.clone(),
+                is_dir: false,
+            });
+        }
+        for name in &node.subdirs {
+            self.entries.push(FileEntry {
+                path: dir.join(name),
+                name: name.clone(),
+                is_dir: true,
+            });
+        }
+
+        let subdirs = node.subdirs.clone();
+        let has_gitignore = node.gitignore.is_some();
+        if let Some(gitignore) = &node.gitignore {
+            gitignores.push(gitignore.clone());
+        }
+        self.seen.insert(dir.to_path_buf(), node);
+
+        for name in subdirs {
+            self.visit(&dir.join(name), gitignores, stale);
+        }
+        if has_gitignore {
+            gitignores.pop();
+        }
+    }
+
+    fn scan(&self, dir: &Path, mtime: SystemTime, gitignores: &[Arc<Gitignore>]) -> DirNode {
+        let gitignore = if self.index.conf.gitignore {
+            read_gitignore(dir).map(Arc::new)
+        } else {
+            None
+        };
+
+        let mut stack = gitignores.to_vec();
+        stack.extend(gitignore.clone());
+
+        let mut node = DirNode {
+            mtime,
+            gitignore_mtime: None,
+            files: vec![],
+            subdirs: vec![],
+            gitignore: None,
+        };
+        let Ok(read_dir) = std::fs::read_dir(dir) else {
+            return node;
+        };
+        for entry in read_dir.filter_map(|e| e.ok()) {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = entry.path();
+            // symlinks are listed but never followed
+            let is_dir = file_type.is_dir();
+            if self.index.is_ignored(&path, &name, is_dir, &stack) {
+                continue;
+            }
+
+            if is_dir {
+                node.subdirs.push(name);
+            } else {
+                node.files.push(name);
+            }
+        }
+
+        node.gitignore = gitignore;
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn index(root: &Path, conf: FilesConf) -> FileIndex {
+        FileIndex::new(&FilesConf {
+            roots: vec![root.to_string_lossy().to_string()],
+            ..conf
+        })
+    }
+
+    /// Paths relative to `root`, directories end with a slash.
+    fn listed(root: &Path, entries: &[FileEntry]) -> Vec<String> {
+        let mut listed: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                let path = entry.path.strip_prefix(root).unwrap().display();
+                match entry.is_dir {
+                    true => format!("{path}/"),
+                    false => path.to_string(),
+                }
+            })
+            .collect();
+        listed.sort();
+        listed
+    }
+
+    fn write(root: &Path, path: &str, data: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+
+    /// Moves the mtime of `dir` forward, changes made within the same clock
+    /// tick as the last walk would go unnoticed otherwise.
+    fn touch(dir: &Path) {
+        let mtime = std::fs::metadata(dir).unwrap().modified().unwrap();
+        let dir = std::fs::File::open(dir).unwrap();
+        dir.set_modified(mtime + Duration::from_secs(1)).unwrap();
+    }
+
+    #[test]
+    fn incremental_refresh() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "notes.txt", "");
+        write(root, "src/main.rs", "");
+        write(root, "src/lib.rs", "");
+
+        let mut index = index(root, FilesConf::default());
+        assert_eq!(
+            listed(root, &index.refresh()),
+            ["notes.txt", "src/", "src/lib.rs", "src/main.rs"]
+        );
+
+        write(root, "src/new.rs", "");
+        touch(&root.join("src"));
+        std::fs::rename(root.join("notes.txt"), root.join("todo.txt")).unwrap();
+        touch(root);
+        assert_eq!(
+            listed(root, &index.refresh()),
+            [
+                "src/",
+                "src/lib.rs",
+                "src/main.rs",
+                "src/new.rs",
+                "todo.txt"
+            ]
+        );
+
+        // unchanged directories are not read again
+        std::fs::remove_file(root.join("src/lib.rs")).unwrap();
+        let mtime = std::fs::metadata(root.join("src"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        index.dirs.get_mut(&root.join("src")).unwrap().mtime = mtime;
+        assert!(listed(root, &index.refresh()).contains(&"src/lib.rs".to_string()));
+        touch(&root.join("src"));
+        assert!(!listed(root, &index.refresh()).contains(&"src/lib.rs".to_string()));
+    }
+
+    #[test]
+    fn changed_gitignores_filter_again() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "src/main.rs", "");
+        write(root, "src/debug.log", "");
+        write(root, "src/deeper/trace.log", "");
+
+        let mut index = index(root, FilesConf::default());
+        assert!(listed(root, &index.refresh()).contains(&"src/debug.log".to_string()));
+
+        // a new .gitignore at the root, the subdirectories did not change
+        write(root, ".gitignore", "*.log\n");
+        touch(root);
+        assert_eq!(
+            listed(root, &index.refresh()),
+            ["src/", "src/deeper/", "src/main.rs"]
+        );
+
+        // edited in place, the directory keeps its mtime
+        let mtime = std::fs::metadata(root).unwrap().modified().unwrap();
+        write(root, ".gitignore", "trace.log\n");
+        touch(&root.join(".gitignore"));
+        std::fs::File::open(root)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert_eq!(
+            listed(root, &index.refresh()),
+            ["src/", "src/debug.log", "src/deeper/", "src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn gitignores_stack() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, ".gitignore", "*.log\ntarget/\n");
+        write(root, "app.log", "");
+        write(root, "target/debug", "");
+        write(root, "sub/.gitignore", "!keep.log\nout\n");
+        write(root, "sub/keep.log", "");
+        write(root, "sub/other.log", "");
+        write(root, "sub/out/a", "");
+        write(root, "sub/deeper/keep.log", "");
+        write(root, "sibling/out/b", "");
+
+        let mut index = index(root, FilesConf::default());
+        assert_eq!(
+            listed(root, &index.refresh()),
+            [
+                "sibling/",
+                "sibling/out/",
+                "sibling/out/b",
+                "sub/",
+                "sub/deeper/",
+                "sub/deeper/keep.log",
+                "sub/keep.log",
+            ]
+        );
+
+        let mut index = self::index(
+            root,
+            FilesConf {
+                gitignore: false,
+                ..FilesConf::default()
+            },
+        );
+        assert!(listed(root, &index.refresh()).contains(&"target/debug".to_string()));
+    }
+
+    #[test]
+    fn hidden_files_and_ignore_globs() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, ".config/app.conf", "");
+        write(root, ".git/HEAD", "");
+        write(root, "a.tmp", "");
+        write(root, "web/node_modules/dep/index.js", "");
+        write(root, "web/index.html", "");
+
+        let mut index = index(root, FilesConf::default());
+        assert_eq!(
+            listed(root, &index.refresh()),
+            [
+                "a.tmp",
+                "web/",
+                "web/index.html",
+                "web/node_modules/",
+                "web/node_modules/dep/",
+                "web/node_modules/dep/index.js",
+            ]
+        );
+
+        let mut index = self::index(
+            root,
+            FilesConf {
+                hidden: true,
+                ignore: vec!["*.tmp".to_string(), "**/web/node_modules".to_string()],
+                ..FilesConf::default()
+            },
+        );
+        assert_eq!(
+            listed(root, &index.refresh()),
+            [".config/", ".config/app.conf", "web/", "web/index.html"]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Read, Write},
+    path::PathBuf,
+};
+use tracing::warn;
+
+const LAUNCHES_FILE: &str = "launches";
+const COMMANDS_FILE: &str = "commands";
+const COMMANDS_LIMIT: usize = 200;
+
+pub fn state_dir() -> PathBuf {
+    let raw_path = std::env::var("XDG_STATE_HOME")
+        .unwrap_or(format!("{}/.local/state", std::env::var("HOME").unwrap()));
+    std::path::Path::new(&raw_path).join("seekr")
+}
+
+/// Replaces the state file `name` with `data`, written next to it first so a
+/// crash never leaves half a file.
+fn write_state(name: &str, data: &str) -> std::io::Result<()> {
+    let dir = state_dir();
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir)?;
+    }
+
+    let tmp = dir.join(format!("{name}.tmp"));
+    std::fs::File::create(&tmp).and_then(|mut f| f.write_all(data.as_bytes()))?;
+    std::fs::rename(&tmp, dir.join(name))
+}
+
+/// How many times each app got launched from seekr, keyed by desktop file id.
+#[derive(Default)]
+pub struct LaunchHistory {
+    counts: HashMap<String, u32>,
+}
+
+impl LaunchHistory {
+    pub fn load() -> Self {
+        let mut history = Self::default();
+        let Ok(mut f) = std::fs::File::open(state_dir().join(LAUNCHES_FILE)) else {
+            return history;
+        };
+
+        let mut data = String::new();
+        let _ = f.read_to_string(&mut data);
+        for line in data.lines() {
+            if let Some((count, id)) = line.split_once('\t') {
+                if let Ok(count) = count.parse() {
+                    history.counts.insert(id.to_string(), count);
+                }
+            }
+        }
+
+        history
+    }
+
+    pub fn count(&self, id: &str) -> u32 {
+        self.counts.get(id).copied().unwrap_or(0)
+    }
+
+    pub fn record(&mut self, id: &str) {
+        *self.counts.entry(id.to_string()).or_insert(0) += 1;
+        self.store();
+    }
+
+    fn store(&self) {
+        let mut data = String::new();
+        for (id, count) in &self.counts {
+            data.push_str(&format!("{count}\t{id}\n"));
+        }
+
+        if let Err(e) = write_state(LAUNCHES_FILE, &data) {
+            warn!("unable to store launch history: {e}");
+        }
+    }
+}
+
+/// Shell commands run from seekr, most recent first.
+#[derive(Clone, Default)]
+pub struct CommandHistory {
+    commands: VecDeque<String>,
+}
+
+impl FromIterator<String> for CommandHistory {
+    fn from_iter<I: IntoIterator<Item = String>>(commands: I) -> Self {
+        Self {
+            commands: commands.into_iter().collect(),
+        }
+    }
+}
+
+impl CommandHistory {
+    pub fn load() -> Self {
+        let data = std::fs::read_to_string(state_dir().join(COMMANDS_FILE)).unwrap_or_default();
+        Self::parse(&data)
+    }
+
+    /// One command per line, most recent first.
+    fn parse(data: &str) -> Self {
+        data.lines()
+            .filter(|line| !line.trim().is_empty())
+            .take(COMMANDS_LIMIT)
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &String> {
+        self.commands.iter()
+    }
+
+    /// Moves `command` to the front and stores the history.
+    pub fn record(&mut self, command: &str) {
+        if self.push(command) {
+            self.store();
+        }
+    }
+
+    /// Moves `command` to the front, it is only kept once. Returns whether
+    /// it got in, empty and multi-line commands don't.
+    fn push(&mut self, command: &str) -> bool {
+        let command = command.trim();
+        if command.is_empty() || command.contains('\n') {
+            return false;
+        }
+        self.commands.retain(|known| known != command);
+        self.commands.push_front(command.to_string());
+        self.commands.truncate(COMMANDS_LIMIT);
+        true
+    }
+
+    fn store(&self) {
+        let mut data = String::new();
+        for command in &self.commands {
+            data.push_str(command);
+            data.push('\n');
+        }
+
+        if let Err(e) = write_state(COMMANDS_FILE, &data) {
+            warn!("unable to store command history: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed(history: &CommandHistory) -> Vec<&str> {
+        history.commands().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn duplicates_move_to_the_front() {
+        let mut history: CommandHistory = ["make", "htop", "git status"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        assert!(history.push("  git status "));
+        assert_eq!(listed(&history), ["git status", "make", "htop"]);
+        assert!(history.push("cargo test"));
+        assert_eq!(
+            listed(&history),
+            ["cargo test", "git status", "make", "htop"]
+        );
+    }
+
+    #[test]
+    fn empty_and_multi_line_commands_are_refused() {
+        let mut history = CommandHistory::default();
+        assert!(!history.push(""));
+        assert!(!history.push("   "));
+        assert!(!history.push("echo a\necho b"));
+        assert_eq!(listed(&history), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn limited() {
+        let mut history: CommandHistory = (0..COMMANDS_LIMIT).map(|i| format!("cmd {i}")).collect();
+        history.push("newest");
+        assert_eq!(history.commands().count(), COMMANDS_LIMIT);
+        assert_eq!(history.commands().next().unwrap(), "newest");
+        assert_eq!(
+            history.commands().last().unwrap(),
+            &format!("cmd {}", COMMANDS_LIMIT - 2)
+        );
+
+        let data: String = (0..COMMANDS_LIMIT + 10)
+            .map(|i| format!("cmd {i}\n\n"))
+            .collect();
+        let loaded = CommandHistory::parse(&data);
+        assert_eq!(loaded.commands().count(), COMMANDS_LIMIT);
+        assert_eq!(loaded.commands().next().unwrap(), "cmd 0");
+    }
+}
@@ -20,20 +20,49 @@ pub fn load_image_resource(content: &[u8], size: (i32, i32)) -> Icon {
     )
 }
 
-pub fn get_icon(name_or_path: &str) -> Icon {
+/// Looks `key` up in the icon cache, `load` builds the icon on a miss.
+fn cached(key: &str, load: impl FnOnce() -> Icon) -> Icon {
     let mut res = resources::ICON_MAP.get().write().unwrap();
-    if let Some(e) = res.get(name_or_path) {
-        return e.clone();
-    } else {
-        if std::path::Path::new(name_or_path).exists() {
-            if let Ok(mut f) = std::fs::File::open(std::path::Path::new(name_or_path)) {
+    if let Some(icon) = res.get(key) {
+        return icon.clone();
+    }
+    let icon = load();
+    res.insert(key.to_string(), icon.clone());
+    icon
+}
+
+pub fn get_icon(name_or_path: &str) -> Icon {
+    cached(name_or_path, || {
+        let path = std::path::Path::new(name_or_path);
+        if path.exists() {
+            if let Ok(mut f) = std::fs::File::open(path) {
                 let mut buf = vec![];
                 let _ = f.read_to_end(&mut buf);
                 return load_image_resource(&buf, (512, 512));
             }
         }
-        let icon = Icon::from(gio::ThemedIcon::from_names(&[name_or_path]));
-        res.insert(name_or_path.to_string(), icon.clone());
-        return icon;
+        Icon::from(gio::ThemedIcon::from_names(&[name_or_path]))
+    })
+}
+
+fn content_type_icon(content_type: &str) -> Icon {
+    cached(&format!("content-type:{content_type}"), || {
+        gio::content_type_get_icon(content_type)
+    })
+}
+
+/// The icon of the content type guessed from the file name.
+pub fn file_icon(path: &std::path::Path, is_dir: bool) -> Icon {
+    if is_dir {
+        return get_icon("folder");
     }
+    let (content_type, _) = gio::content_type_guess(Some(path), &[]);
+    content_type_icon(&content_type)
+}
+
+/// The icon of a MIME type, a generic one for unknown types.
+pub fn mime_icon(mime_type: &str) -> Icon {
+    let content_type = gio::content_type_from_mime_type(mime_type)
+        .unwrap_or_else(|| "application/octet-stream".into());
+    content_type_icon(&content_type)
 }
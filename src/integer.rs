@@ -0,0 +1,422 @@
+use crate::calc::Answer;
+use crate::conf::CalcConf;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+
+// results and operands past this size are refused, `2^99999999` would
+// otherwise freeze the search
+const MAX_BITS: u64 = 1 << 16;
+
+// binding powers, from the loosest to the tightest
+const PREC_OR: u8 = 1;
+const PREC_XOR: u8 = 2;
+const PREC_AND: u8 = 3;
+const PREC_SHIFT: u8 = 4;
+const PREC_SUM: u8 = 5;
+const PREC_PRODUCT: u8 = 6;
+const PREC_UNARY: u8 = 7;
+const PREC_POWER: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Base {
+    Hex,
+    Dec,
+    Oct,
+    Bin,
+}
+
+impl Base {
+    const ALL: [Base; 4] = [Base::Hex, Base::Dec, Base::Oct, Base::Bin];
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "hex" => Some(Self::Hex),
+            "dec" => Some(Self::Dec),
+            "oct" => Some(Self::Oct),
+            "bin" => Some(Self::Bin),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Hex => "hex",
+            Self::Dec => "dec",
+            Self::Oct => "oct",
+            Self::Bin => "bin",
+        }
+    }
+
+    /// The radix, literal prefix and digit group size.
+    fn layout(self) -> (u32, &'static str, usize) {
+        match self {
+            Self::Hex => (16, "0x", 4),
+            Self::Dec => (10, "", 3),
+            Self::Oct => (8, "0o", 3),
+            Self::Bin => (2, "0b", 4),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// the value and the literal as typed
+    Number(BigInt, String),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+/// Evaluates `query` with arbitrary precision integers. Only queries that
+/// need it are handled: a `0x`/`0o`/`0b` literal, a bitwise operator or a
+/// trailing `in hex|bin|oct|dec`, everything else is left to the float
+/// calculator. `lookup` resolves the variables.
+pub fn evaluate(
+    query: &str,
+    conf: &CalcConf,
+    lookup: impl Fn(&str) -> Option<f64>,
+) -> Option<Answer> {
+    let (expr, base) = match query.trim().rsplit_once(" in ") {
+        Some((expr, base)) => (expr, Some(Base::parse(base.trim())?)),
+        None => (query, None),
+    };
+
+    let (tokens, prefixed) = tokenize(expr)?;
+    let bitwise = tokens
+        .iter()
+        .any(|token| matches!(token, Token::Op("<<" | ">>" | "&" | "|" | "~" | "xor")));
+    if base.is_none() && !prefixed && !bitwise {
+        return None;
+    }
+
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        lookup,
+    };
+    let result = parser.expr(0)?;
+    if parser.pos != tokens.len() {
+        return None;
+    }
+
+    let expr = match base {
+        Some(base) => format!("{} in {}", render(&tokens), base.name()),
+        None => render(&tokens),
+    };
+    let base = base.unwrap_or(Base::Dec);
+
+    Some(Answer {
+        expr,
+        value: format_radix(&result, base, separator(base, conf)),
+        plain: format_radix(&result, base, ""),
+        result: result.to_f64().unwrap_or(f64::NAN),
+        assignments: vec![],
+        bases: Base::ALL
+            .iter()
+            .map(|base| {
+                (
+                    base.name().to_string(),
+                    format_radix(&result, *base, separator(*base, conf)),
+                )
+            })
+            .collect(),
+    })
+}
+
+fn separator(base: Base, conf: &CalcConf) -> &str {
+    match base {
+        Base::Dec => &conf.thousands_separator,
+        _ => "_",
+    }
+}
+
+fn format_radix(x: &BigInt, base: Base, separator: &str) -> String {
+    let (radix, prefix, group) = base.layout();
+    let digits = x.magnitude().to_str_radix(radix);
+
+    let mut grouped = String::with_capacity(digits.len() * 2);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % group == 0 {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+
+    let sign = if x.is_negative() { "-" } else { "" };
+    format!("{sign}{prefix}{grouped}")
+}
+
+/// Returns the tokens of `expr` and whether it holds a prefixed literal,
+/// `None` if something is not an integer expression.
+fn tokenize(expr: &str) -> Option<(Vec<Token>, bool)> {
+    let mut tokens = vec![];
+    let mut prefixed = false;
+    let mut rest = expr.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '0'..='9' => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let literal = rest[..len].replace('_', "").to_lowercase();
+                let (radix, digits) = match literal.get(..2) {
+                    Some("0x") => (16, &literal[2..]),
+                    Some("0o") => (8, &literal[2..]),
+                    Some("0b") => (2, &literal[2..]),
+                    _ => (10, literal.as_str()),
+                };
+                prefixed |= radix != 10;
+                let value = BigInt::parse_bytes(digits.as_bytes(), radix)?;
+                tokens.push(Token::Number(value, rest[..len].to_string()));
+                len
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                match &rest[..len] {
+                    "xor" => tokens.push(Token::Op("xor")),
+                    ident => tokens.push(Token::Ident(ident.to_string())),
+                }
+                len
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                1
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                1
+            }
+            _ => {
+                let op = ["<<", ">>", "+", "-", "*", "/", "%", "^", "&", "|", "~"]
+                    .into_iter()
+                    .find(|op| rest.starts_with(op))?;
+                tokens.push(Token::Op(op));
+                op.len()
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Some((tokens, prefixed))
+}
+
+/// Writes `tokens` back with a single space around binary operators.
+fn render(tokens: &[Token]) -> String {
+    let mut res = String::new();
+    // whether the next operator follows an operand, making it a binary one
+    let mut after_operand = false;
+
+    for token in tokens {
+        match token {
+            Token::Number(_, literal) => {
+                res.push_str(literal);
+                after_operand = true;
+            }
+            Token::Ident(ident) => {
+                res.push_str(ident);
+                after_operand = true;
+            }
+            Token::Op(op) if after_operand => {
+                res.push_str(&format!(" {op} "));
+                after_operand = false;
+            }
+            Token::Op(op) => res.push_str(op),
+            Token::Open => res.push('('),
+            Token::Close => {
+                res.push(')');
+                after_operand = true;
+            }
+        }
+    }
+
+    res
+}
+
+fn binary_precedence(op: &str) -> Option<(u8, bool)> {
+    let (prec, right_assoc) = match op {
+        "|" => (PREC_OR, false),
+        "xor" => (PREC_XOR, false),
+        "&" => (PREC_AND, false),
+        "<<" | ">>" => (PREC_SHIFT, false),
+        "+" | "-" => (PREC_SUM, false),
+        "*" | "/" | "%" => (PREC_PRODUCT, false),
+        "^" => (PREC_POWER, true),
+        _ => return None,
+    };
+    Some((prec, right_assoc))
+}
+
+fn too_large(x: &BigInt) -> bool {
+    x.bits() > MAX_BITS
+}
+
+fn apply(op: &str, lhs: BigInt, rhs: BigInt) -> Option<BigInt> {
+    let res = match op {
+        "+" => lhs + rhs,
+        "-" => lhs - rhs,
+        "*" => lhs * rhs,
+        "/" | "%" if rhs.is_zero() => return None,
+        "/" => lhs / rhs,
+        "%" => lhs % rhs,
+        "^" => {
+            let exp = rhs.to_u32()?;
+            if lhs.bits().saturating_mul(exp as u64) > MAX_BITS {
+                return None;
+            }
+            lhs.pow(exp)
+        }
+        "<<" => {
+            let shift = rhs.to_u64().filter(|shift| *shift <= MAX_BITS)?;
+            lhs << shift
+        }
+        ">>" => lhs >> rhs.to_u64()?,
+        "&" => lhs & rhs,
+        "|" => lhs | rhs,
+        "xor" => lhs ^ rhs,
+        _ => return None,
+    };
+
+    (!too_large(&res)).then_some(res)
+}
+
+struct Parser<'a, F> {
+    tokens: &'a [Token],
+    pos: usize,
+    lookup: F,
+}
+
+impl<F: Fn(&str) -> Option<f64>> Parser<'_, F> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// Parses operators binding at least as tight as `min_prec`.
+    fn expr(&mut self, min_prec: u8) -> Option<BigInt> {
+        let mut lhs = self.unary()?;
+
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos) {
+            let (prec, right_assoc) = binary_precedence(op)?;
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(if right_assoc { prec } else { prec + 1 })?;
+            lhs = apply(op, lhs, rhs)?;
+        }
+
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<BigInt> {
+        match self.next()?.clone() {
+            Token::Op("-") => Some(-self.expr(PREC_UNARY)?),
+            Token::Op("+") => self.expr(PREC_UNARY),
+            Token::Op("~") => Some(!self.expr(PREC_UNARY)?),
+            Token::Number(n, _) => Some(n),
+            Token::Ident(name) => {
+                // variables are floats, only whole ones make sense here
+                let value = (self.lookup)(&name).filter(|value| value.fract() == 0.0)?;
+                BigInt::from_f64(value)
+            }
+            Token::Open => {
+                let inner = self.expr(0)?;
+                (self.next()? == &Token::Close).then_some(inner)
+            }
+            Token::Op(_) | Token::Close => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(query: &str) -> Option<String> {
+        evaluate(query, &CalcConf::default(), |_| None).map(|answer| answer.value)
+    }
+
+    #[test]
+    fn only_integer_queries() {
+        assert_eq!(value("1 + 2"), None);
+        assert_eq!(value("0x10 + 2").as_deref(), Some("18"));
+        assert_eq!(value("6 & 3").as_deref(), Some("2"));
+        assert_eq!(value("3 in dec").as_deref(), Some("3"));
+        assert_eq!(value("0x10 +"), None);
+        assert_eq!(value("(0x10"), None);
+        assert_eq!(value("0x1g"), None);
+        assert_eq!(value("1.5 in hex"), None);
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("0x10 + 2 * 3").as_deref(), Some("22"));
+        assert_eq!(value("(1 + 2) * 3 in dec").as_deref(), Some("9"));
+        // shifts bind looser than sums, bitwise operators looser still
+        assert_eq!(value("1 << 2 + 1").as_deref(), Some("8"));
+        assert_eq!(value("6 & 3 | 8").as_deref(), Some("10"));
+        assert_eq!(value("5 xor 1 & 3").as_deref(), Some("4"));
+        assert_eq!(value("1 | 6 xor 2").as_deref(), Some("5"));
+        assert_eq!(value("10 - 4 - 3 in dec").as_deref(), Some("3"));
+        assert_eq!(value("100 / 10 / 5 in dec").as_deref(), Some("2"));
+        assert_eq!(value("2 ^ 3 ^ 2 in dec").as_deref(), Some("512"));
+        assert_eq!(value("-2 ^ 2 in dec").as_deref(), Some("-4"));
+        assert_eq!(value("~0 in dec").as_deref(), Some("-1"));
+        assert_eq!(value("-7 % 3 in dec").as_deref(), Some("-1"));
+        assert_eq!(value("1 / 0 in dec"), None);
+    }
+
+    #[test]
+    fn bases() {
+        assert_eq!(value("255 in hex").as_deref(), Some("0xff"));
+        assert_eq!(value("0b1010_1010 in dec").as_deref(), Some("170"));
+        assert_eq!(value("-5 in bin").as_deref(), Some("-0b101"));
+        assert_eq!(value("0o777 in oct").as_deref(), Some("0o777"));
+        assert_eq!(value("1000000 in dec").as_deref(), Some("1,000,000"));
+        assert_eq!(value("3 in base7"), None);
+
+        let answer = evaluate("0xdeadbeef+1", &CalcConf::default(), |_| None).unwrap();
+        assert_eq!(answer.expr, "0xdeadbeef + 1");
+        assert_eq!(answer.value, "3,735,928,560");
+        assert_eq!(answer.plain, "3735928560");
+        assert_eq!(
+            answer.bases,
+            [
+                ("hex".to_string(), "0xdead_bef0".to_string()),
+                ("dec".to_string(), "3,735,928,560".to_string()),
+                ("oct".to_string(), "0o33_653_337_360".to_string()),
+                (
+                    "bin".to_string(),
+                    "0b1101_1110_1010_1101_1011_1110_1111_0000".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn max_bits() {
+        assert!(value("1 << 65535 in hex").is_some());
+        assert_eq!(value("1 << 65536 in hex"), None);
+        assert_eq!(value("1 << 99999999999 in hex"), None);
+        assert_eq!(value("0x10 ^ 99999999"), None);
+        assert_eq!(value("(1 << 65535) * 2 in hex"), None);
+    }
+
+    #[test]
+    fn variables() {
+        let lookup = |name: &str| match name {
+            "x" => Some(3.0),
+            "half" => Some(0.5),
+            _ => None,
+        };
+        let value = |query| evaluate(query, &CalcConf::default(), lookup).map(|a| a.value);
+        assert_eq!(value("x << 2").as_deref(), Some("12"));
+        assert_eq!(value("half << 2"), None);
+        assert_eq!(value("y << 2"), None);
+    }
+}
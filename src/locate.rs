@@ -0,0 +1,364 @@
+use crate::conf::LocateConf;
+use crate::files::{expand_home, FileEntry};
+use ruzstd::decoding::dictionary::Dictionary;
+use ruzstd::{BlockDecodingStrategy, FrameDecoder};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+const MLOCATE_MAGIC: &[u8] = b"\0mlocate";
+const PLOCATE_MAGIC: &[u8] = b"\0plocate";
+
+/// A path listed by a locate database.
+#[derive(Debug, Clone)]
+pub struct Located {
+    pub path: Box<str>,
+    /// `None` when the database does not tell, plocate only knows the
+    /// directories holding something
+    pub is_dir: Option<bool>,
+}
+
+impl Located {
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    /// Checks the file system for what the database does not tell, only
+    /// the entries shown get there.
+    pub fn to_entry(&self) -> FileEntry {
+        let path = PathBuf::from(&*self.path);
+        FileEntry {
+            is_dir: self.is_dir.unwrap_or_else(|| path.is_dir()),
+            name: self.name().to_string(),
+            path,
+        }
+    }
+}
+
+/// The paths of the first readable `[locate]` database, read again only
+/// when its mtime changes.
+pub struct LocateIndex {
+    conf: LocateConf,
+    loaded: Option<(PathBuf, SystemTime)>,
+    entries: Arc<Vec<Located>>,
+}
+
+impl LocateIndex {
+    pub fn new(conf: &LocateConf) -> Self {
+        Self {
+            conf: conf.clone(),
+            loaded: None,
+            entries: Arc::new(vec![]),
+        }
+    }
+
+    pub fn conf(&self) -> &LocateConf {
+        &self.conf
+    }
+
+    pub fn refresh(&mut self) -> Arc<Vec<Located>> {
+        if !self.conf.enabled {
+            return self.entries.clone();
+        }
+
+        for database in &self.conf.databases {
+            let path = expand_home(database);
+            let mtime = match std::fs::metadata(&path).and_then(|meta| meta.modified()) {
+                Ok(mtime) => mtime,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => {
+                    warn!("{}: {e}", path.display());
+                    continue;
+                }
+            };
+            if self.loaded.as_ref() == Some(&(path.clone(), mtime)) {
+                return self.entries.clone();
+            }
+
+            match read_database(&path) {
+                Ok(entries) => {
+                    debug!("read {} paths from {}", entries.len(), path.display());
+                    self.loaded = Some((path, mtime));
+                    self.entries = Arc::new(entries);
+                    return self.entries.clone();
+                }
+                Err(e) => warn!("{}: {e}", path.display()),
+            }
+        }
+
+        // none of the databases can be read anymore
+        self.loaded = None;
+        self.entries = Arc::new(vec![]);
+        self.entries.clone()
+    }
+}
+
+fn read_database(path: &Path) -> Result<Vec<Located>, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    if data.starts_with(MLOCATE_MAGIC) {
+        read_mlocate(&data).ok_or_else(|| "truncated mlocate database".to_string())
+    } else if data.starts_with(PLOCATE_MAGIC) {
+        read_plocate(&data)
+    } else {
+        Err("not a plocate or mlocate database".to_string())
+    }
+}
+
+/// Reads integers and C strings out of a database.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32_be(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u32_le(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64_le(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn cstr(&mut self) -> Option<&'a [u8]> {
+        let len = self.data.get(self.pos..)?.iter().position(|&b| b == 0)?;
+        let s = &self.data[self.pos..self.pos + len];
+        self.pos += len + 1;
+        Some(s)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+/// Joins a directory and a name, non UTF-8 paths are left out.
+fn join(dir: &str, name: &[u8]) -> Option<Box<str>> {
+    let name = std::str::from_utf8(name).ok()?;
+    let path = if dir.ends_with('/') {
+        format!("{dir}{name}")
+    } else {
+        format!("{dir}/{name}")
+    };
+    Some(path.into_boxed_str())
+}
+
+/// mlocate.db: a header, then for every directory its path followed by the
+/// names it contains.
+fn read_mlocate(data: &[u8]) -> Option<Vec<Located>> {
+    let mut reader = Reader { data, pos: 0 };
+    reader.bytes(MLOCATE_MAGIC.len())?;
+    let conf_size = reader.u32_be()? as usize;
+    // version, visibility flag and padding
+    reader.bytes(4)?;
+    // the root, listed again as the first directory
+    reader.cstr()?;
+    reader.bytes(conf_size)?;
+
+    let mut entries = vec![];
+    while !reader.is_empty() {
+        // the directory mtime and padding
+        reader.bytes(16)?;
+        let dir = reader.cstr()?;
+        let dir = std::str::from_utf8(dir).ok();
+        loop {
+            let is_dir = match reader.u8()? {
+                0 => false,
+                1 => true,
+                2 => break,
+                _ => return None,
+            };
+            let name = reader.cstr()?;
+            if let Some(path) = dir.and_then(|dir| join(dir, name)) {
+                entries.push(Located {
+                    path,
+                    is_dir: Some(is_dir),
+                });
+            }
+        }
+    }
+    Some(entries)
+}
+
+/// plocate.db: a little-endian header pointing to zstd compressed blocks of
+/// NUL separated paths, optionally sharing a dictionary.
+fn read_plocate(data: &[u8]) -> Result<Vec<Located>, String> {
+    let truncated = || "truncated plocate database".to_string();
+
+    let mut reader = Reader { data, pos: 0 };
+    reader.bytes(PLOCATE_MAGIC.len()).ok_or_else(truncated)?;
+    let version = reader.u32_le().ok_or_else(truncated)?;
+    // hash table size and extra slots
+    reader.bytes(8).ok_or_else(truncated)?;
+    let num_docids = reader.u32_le().ok_or_else(truncated)? as usize;
+    // hash table offset
+    reader.bytes(8).ok_or_else(truncated)?;
+    let filename_index_offset = reader.u64_le().ok_or_else(truncated)? as usize;
+
+    let mut decoder = FrameDecoder::new();
+    if version >= 1 {
+        // max version
+        reader.bytes(4).ok_or_else(truncated)?;
+        let dictionary_len = reader.u32_le().ok_or_else(truncated)? as usize;
+        let dictionary_offset = reader.u64_le().ok_or_else(truncated)? as usize;
+        if dictionary_len > 0 {
+            let raw = dictionary_offset
+                .checked_add(dictionary_len)
+                .and_then(|end| data.get(dictionary_offset..end))
+                .ok_or_else(truncated)?;
+            let dictionary = Dictionary::decode_dict(raw).map_err(|e| e.to_string())?;
+            decoder.add_dict(dictionary).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let mut offsets = Reader {
+        data,
+        pos: filename_index_offset,
+    };
+    let offsets = (0..=num_docids)
+        .map(|_| offsets.u64_le().map(|offset| offset as usize))
+        .collect::<Option<Vec<usize>>>()
+        .ok_or_else(truncated)?;
+
+    let mut paths: Vec<Box<str>> = vec![];
+    for block in offsets.windows(2) {
+        let mut block = data.get(block[0]..block[1]).ok_or_else(truncated)?;
+        decoder.reset(&mut block).map_err(|e| e.to_string())?;
+        decoder
+            .decode_blocks(&mut block, BlockDecodingStrategy::All)
+            .map_err(|e| e.to_string())?;
+        let Some(names) = decoder.collect() else {
+            continue;
+        };
+        paths.extend(
+            names
+                .split(|&b| b == 0)
+                .filter(|path| !path.is_empty())
+                .filter_map(|path| std::str::from_utf8(path).ok())
+                .map(Box::from),
+        );
+    }
+
+    // only paths are stored, a directory is followed by its content while
+    // an empty one can't be told from a file
+    let is_dir: Vec<Option<bool>> = paths
+        .windows(2)
+        .map(|pair| {
+            let (path, next) = (&pair[0], &pair[1]);
+            next.strip_prefix(&**path)
+                .is_some_and(|rest| rest.starts_with('/'))
+                .then_some(true)
+        })
+        .chain([None])
+        .collect();
+    Ok(paths
+        .into_iter()
+        .zip(is_dir)
+        .map(|(path, is_dir)| Located { path, is_dir })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<Located> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/locate")
+            .join(name);
+        read_database(&path).unwrap()
+    }
+
+    fn listed(entries: &[Located]) -> Vec<(&str, Option<bool>)> {
+        entries
+            .iter()
+            .map(|entry| (&*entry.path, entry.is_dir))
+            .collect()
+    }
+
+    #[test]
+    fn mlocate() {
+        assert_eq!(
+            listed(&fixture("mlocate.db")),
+            [
+                ("/home/user/docs", Some(true)),
+                ("/home/user/empty", Some(true)),
+                ("/home/user/music", Some(true)),
+                ("/home/user/todo.txt", Some(false)),
+                ("/home/user/café.txt", Some(false)),
+                ("/home/user/docs/notes.txt", Some(false)),
+                ("/home/user/docs/report.pdf", Some(false)),
+                ("/home/user/music/song.mp3", Some(false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn plocate() {
+        let expected = [
+            ("/home/user", Some(true)),
+            ("/home/user/docs", Some(true)),
+            ("/home/user/docs/notes.txt", None),
+            ("/home/user/docs/report.pdf", None),
+            // empty, nothing follows it
+            ("/home/user/empty", None),
+            ("/home/user/music", Some(true)),
+            ("/home/user/music/song.mp3", None),
+            ("/home/user/todo.txt", None),
+            ("/home/user/café.txt", None),
+        ];
+        assert_eq!(listed(&fixture("plocate.db")), expected);
+        // blocks compressed against a shared zstd dictionary
+        assert_eq!(listed(&fixture("plocate-dictionary.db")), expected);
+    }
+
+    #[test]
+    fn unknown_kinds_are_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("empty")).unwrap();
+        std::fs::write(dir.path().join("file"), "").unwrap();
+
+        let entry = |name: &str| {
+            Located {
+                path: dir.path().join(name).to_string_lossy().into(),
+                is_dir: None,
+            }
+            .to_entry()
+        };
+        assert!(entry("empty").is_dir);
+        assert!(!entry("file").is_dir);
+        assert_eq!(entry("empty").name, "empty");
+    }
+
+    #[test]
+    fn broken_databases() {
+        let data = std::fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/locate/plocate-dictionary.db"),
+        )
+        .unwrap();
+        assert!(read_plocate(&data[..data.len() - 8]).is_err());
+        // a dictionary offset running past the end of the address space
+        let mut hostile = data.clone();
+        hostile[48..56].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read_plocate(&hostile).is_err());
+        let data =
+            std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/locate/mlocate.db"))
+                .unwrap();
+        assert!(read_mlocate(&data[..data.len() - 1]).is_none());
+    }
+}
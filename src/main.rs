@@ -3,16 +3,38 @@ use gtk::prelude::*;
 use gtk::{Application, ApplicationWindow};
 use rust_i18n::t;
 use search::SearchManager;
+use std::cell::Cell;
 use tokio::runtime::Runtime;
+use tokio::sync::mpsc::UnboundedSender;
 
 mod app;
+mod browser;
 mod bus;
+mod cache;
+mod calc;
+mod cli;
 mod conf;
+mod dates;
+mod executables;
+mod files;
+mod history;
 mod icons;
+mod integer;
 mod locale;
+mod locate;
+mod mime;
+mod paths;
+mod recent;
 mod resources;
 mod search;
+mod shell;
+mod ssh;
+mod tz;
 mod ui;
+mod units;
+mod url;
+mod websearch;
+mod windows;
 
 rust_i18n::i18n!("locales", fallback = "en");
 
@@ -37,7 +59,7 @@ fn activate(config: conf::Config, app: &Application) {
 
     window.set_default_size(600, -1);
 
-    let (manager, (tomanager, frommanager)) = SearchManager::new();
+    let (manager, (tomanager, frommanager)) = SearchManager::new(&config);
     manager.manage();
 
     let entry = gtk::Entry::builder()
@@ -51,15 +73,37 @@ fn activate(config: conf::Config, app: &Application) {
     represent_action.connect_activate(glib::clone!(
         #[weak]
         window,
+        #[weak]
+        entry,
         #[strong]
         tomanager,
         move |_, _| {
             let _ = tomanager.send(search::SearchEvent::Represent);
+            // the config may have changed, refresh what is displayed, the
+            // manager searches again once the apps are reloaded
+            let _ = tomanager.send(search::SearchEvent::Term(entry.text().to_string()));
             window.present();
         }
     ));
     window.add_action(&represent_action);
 
+    // recent files opened meanwhile show up in the displayed results
+    let recent_monitor = recent::watch(glib::clone!(
+        #[weak]
+        entry,
+        #[strong]
+        tomanager,
+        move || {
+            let _ = tomanager.send(search::SearchEvent::RecentChanged);
+            let _ = tomanager.send(search::SearchEvent::Term(entry.text().to_string()));
+        }
+    ));
+    window.connect_destroy(move |_| {
+        if let Some(monitor) = &recent_monitor {
+            monitor.cancel();
+        }
+    });
+
     let input_container = gtk::Box::builder()
         .height_request(60)
         .hexpand(true)
@@ -101,12 +145,52 @@ fn activate(config: conf::Config, app: &Application) {
         .css_name("resultBox")
         .build();
 
-    let result_box = gtk::Box::builder()
+    let store = gtk::gio::ListStore::new::<glib::BoxedAnyObject>();
+    let selection = gtk::SingleSelection::builder()
+        .model(&store)
+        .autoselect(false)
+        .can_unselect(true)
+        .build();
+    let list_view = gtk::ListView::builder()
+        .model(&selection)
+        .factory(&ui::ResultFactory())
+        .single_click_activate(true)
         .hexpand(true)
-        .orientation(gtk::Orientation::Vertical)
         .build();
 
-    scroll_container.set_child(Some(&result_box));
+    list_view.connect_activate(glib::clone!(
+        #[strong]
+        config,
+        #[strong]
+        tomanager,
+        move |view, position| activate_item(&config, &tomanager, view.model(), position, false)
+    ));
+
+    let drawer_store = gtk::gio::ListStore::new::<glib::BoxedAnyObject>();
+    let grid_view = gtk::GridView::builder()
+        .model(
+            &gtk::SingleSelection::builder()
+                .model(&drawer_store)
+                .autoselect(false)
+                .can_unselect(true)
+                .build(),
+        )
+        .factory(&ui::DrawerFactory())
+        .single_click_activate(true)
+        .min_columns(3)
+        .max_columns(5)
+        .hexpand(true)
+        .build();
+
+    grid_view.connect_activate(glib::clone!(
+        #[strong]
+        config,
+        #[strong]
+        tomanager,
+        move |view, position| activate_item(&config, &tomanager, view.model(), position, false)
+    ));
+
+    scroll_container.set_child(Some(&list_view));
     #[allow(deprecated)]
     scroll_container.hide();
 
@@ -114,124 +198,156 @@ fn activate(config: conf::Config, app: &Application) {
     shell.append(&scroll_container);
     window.set_child(Some(&shell));
 
-    let clear_results = glib::clone!(
-        #[strong]
-        result_box,
+    let show_results = glib::clone!(
         #[strong]
         scroll_container,
-        move || {
-            while let Some(child) = result_box.first_child() {
-                result_box.remove(&child);
+        move |sections: Vec<search::Section>| {
+            let mut rows = vec![];
+            let mut drawer = None;
+            for section in sections {
+                match section {
+                    search::Section::Mathematic(res) => rows.push(ui::Row::Mathematic(res)),
+                    search::Section::Apps(entries) => {
+                        if !entries.is_empty() {
+                            rows.push(ui::Row::Title(t!("apps").to_string()));
+                            rows.extend(entries.into_iter().map(ui::Row::App));
+                        }
+                    }
+                    search::Section::Windows(windows) => {
+                        rows.push(ui::Row::Title(t!("windows").to_string()));
+                        rows.extend(windows.into_iter().map(ui::Row::Window));
+                    }
+                    search::Section::Ssh(hosts) => {
+                        rows.push(ui::Row::Title(t!("ssh_hosts").to_string()));
+                        rows.extend(hosts.into_iter().map(ui::Row::Ssh));
+                    }
+                    search::Section::Pages(pages) => {
+                        rows.push(ui::Row::Title(t!("browsers").to_string()));
+                        rows.extend(pages.into_iter().map(ui::Row::Page));
+                    }
+                    search::Section::Executables(executables) => {
+                        rows.push(ui::Row::Title(t!("executables").to_string()));
+                        rows.extend(executables.into_iter().map(ui::Row::Executable));
+                    }
+                    search::Section::Recent(entries) => {
+                        rows.push(ui::Row::Title(t!("recent").to_string()));
+                        rows.extend(entries.into_iter().map(ui::Row::Recent));
+                    }
+                    search::Section::OpenWith { uri, apps } => {
+                        rows.push(ui::Row::Title(t!("open_with").to_string()));
+                        rows.extend(
+                            apps.into_iter()
+                                .map(|app| ui::Row::OpenWith(app, uri.clone())),
+                        );
+                    }
+                    search::Section::Url(link) => rows.push(ui::Row::Url(link)),
+                    search::Section::WebSearch(search) => rows.push(ui::Row::WebSearch(search)),
+                    search::Section::Shell(commands) => {
+                        rows.extend(commands.into_iter().map(ui::Row::Shell))
+                    }
+                    search::Section::Path { dir, entries } => match entries {
+                        Ok(entries) if entries.is_empty() => {}
+                        Ok(entries) => {
+                            rows.push(ui::Row::Title(dir));
+                            rows.extend(entries.into_iter().map(ui::Row::File));
+                        }
+                        Err(std::io::ErrorKind::PermissionDenied) => rows.push(ui::Row::Title(
+                            t!("permission_denied", dir = dir).to_string(),
+                        )),
+                        Err(kind) => rows.push(ui::Row::Title(format!("{dir}: {kind}"))),
+                    },
+                    search::Section::Files(files) => {
+                        rows.push(ui::Row::Title(t!("files").to_string()));
+                        rows.extend(files.into_iter().map(ui::Row::File));
+                    }
+                    search::Section::Drawer(entries) => drawer = Some(entries),
+                }
+            }
+
+            match drawer {
+                Some(entries) => {
+                    if grid_view.parent().is_none() {
+                        scroll_container.set_child(Some(&grid_view));
+                    }
+                    scroll_container.set_visible(!entries.is_empty());
+                    ui::replace_rows(
+                        &drawer_store,
+                        entries.into_iter().map(ui::Row::App).collect(),
+                    );
+                }
+                None => {
+                    if list_view.parent().is_none() {
+                        scroll_container.set_child(Some(&list_view));
+                    }
+                    scroll_container.set_visible(!rows.is_empty());
+                    ui::replace_rows(&store, rows);
+                }
             }
-            result_box.set_css_classes(&[]);
-            #[allow(deprecated)]
-            scroll_container.hide();
         }
     );
 
-    let show_math = glib::clone!(
+    entry.connect_activate(glib::clone!(
+        #[strong]
+        config,
         #[strong]
-        result_box,
+        tomanager,
         #[strong]
         scroll_container,
-        move |res: f64| {
-            #[allow(deprecated)]
-            scroll_container.show();
-            let math_box = gtk::Box::builder()
-                .css_name("mathResult")
-                .hexpand(true)
-                .height_request(395)
-                .orientation(gtk::Orientation::Vertical)
-                .build();
-            let head_box = gtk::Box::builder()
-                .css_classes(["head"])
-                .hexpand(true)
-                .spacing(5)
-                .halign(gtk::Align::Center)
-                .build();
-            let title = gtk::Label::builder()
-                .hexpand(true)
-                .halign(gtk::Align::Start)
-                .ellipsize(gtk::pango::EllipsizeMode::End)
-                .build();
-
-            title.set_text(&t!("expr_eval").to_string());
-            let head_icon = gtk::Image::builder()
-                .pixel_size(12)
-                .gicon(&icons::get_icon("plus-symbolic"))
-                .build();
-            head_icon.set_css_classes(&["eval_icon"]);
-
-            head_box.append(&head_icon);
-            head_box.append(&title);
-
-            let answer_box = gtk::Box::builder()
-                .hexpand(true)
-                .vexpand(true)
-                .css_classes(["answer_box"])
-                .halign(gtk::Align::Center)
-                .valign(gtk::Align::Center)
-                .build();
-
-            let answer = gtk::Label::builder()
-                .css_classes(["answer"])
-                .halign(gtk::Align::Center)
-                .ellipsize(gtk::pango::EllipsizeMode::End)
-                .build();
-            answer.set_text(&format!("{res}"));
-            answer_box.append(&answer);
-
-            math_box.append(&head_box);
-            math_box.append(&answer_box);
-
-            result_box.append(&math_box);
+        move |_| {
+            if let Some((model, position)) = focused_item(&scroll_container) {
+                activate_item(&config, &tomanager, Some(model), position, false);
+            }
         }
-    );
+    ));
 
-    let add_entries = glib::clone!(
+    // Shift+Enter triggers the secondary action of the focused row, Tab
+    // completes a typed path or command with it
+    let key_controller = gtk::EventControllerKey::new();
+    key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+    key_controller.connect_key_pressed(glib::clone!(
         #[strong]
-        result_box,
+        config,
         #[strong]
         tomanager,
         #[strong]
         scroll_container,
-        move |entries: Vec<app::AppEntry>| {
-            let entries_box = gtk::Box::builder()
-                .orientation(gtk::Orientation::Vertical)
-                .spacing(2)
-                .build();
-            if !entries.is_empty() {
-                #[allow(deprecated)]
-                scroll_container.show();
-                let title = gtk::Label::builder()
-                    .hexpand(true)
-                    .halign(gtk::Align::Start)
-                    .ellipsize(gtk::pango::EllipsizeMode::End)
-                    .css_name("title")
-                    .build();
-
-                title.set_label(&t!("apps").to_string());
-                entries_box.append(&title);
+        #[weak]
+        entry,
+        #[upgrade_or]
+        glib::Propagation::Proceed,
+        move |_, key, _, state| {
+            if key == gtk::gdk::Key::Tab {
+                return complete_query(&entry, &scroll_container);
             }
 
-            for entry in entries {
-                let button = ui::EntryButton(&config, entry, &tomanager);
-                entries_box.append(&button);
+            let is_enter = matches!(key, gtk::gdk::Key::Return | gtk::gdk::Key::KP_Enter);
+            if !is_enter || !state.contains(gtk::gdk::ModifierType::SHIFT_MASK) {
+                return glib::Propagation::Proceed;
             }
 
-            result_box.append(&entries_box);
+            if let Some((model, position)) = focused_item(&scroll_container) {
+                activate_item(&config, &tomanager, Some(model), position, true);
+            }
+            glib::Propagation::Stop
         }
-    );
+    ));
+    window.add_controller(key_controller);
 
     window.present();
+    let _ = tomanager.send(search::SearchEvent::Term(String::new()));
 
     {
+        let last_query_id = Cell::new(0);
         glib::spawn_future_local(glib::clone!(async move {
             while let Ok(ev) = frommanager.recv().await {
                 match ev {
-                    search::ManagerEvent::DisplayEntries(entries) => add_entries(entries),
-                    search::ManagerEvent::Mathematic(res) => show_math(res),
-                    search::ManagerEvent::Clear => clear_results(),
+                    search::ManagerEvent::Results { query_id, sections } => {
+                        if query_id < last_query_id.get() {
+                            continue;
+                        }
+                        last_query_id.set(query_id);
+                        show_results(sections);
+                    }
                     search::ManagerEvent::Close => {
                         window.close();
                     }
@@ -241,6 +357,150 @@ fn activate(config: conf::Config, app: &Application) {
     }
 }
 
+/// Runs the action of the row at `position`, `secondary` is set when it got
+/// activated with Shift held.
+fn activate_item(
+    config: &conf::Config,
+    tomanager: &UnboundedSender<search::SearchEvent>,
+    model: Option<gtk::SelectionModel>,
+    position: u32,
+    secondary: bool,
+) {
+    let Some(obj) = model
+        .and_then(|model| model.item(position))
+        .and_downcast::<glib::BoxedAnyObject>()
+    else {
+        return;
+    };
+
+    let row = obj.borrow::<ui::Row>();
+    match &*row {
+        ui::Row::App(entry) => {
+            entry.launch(
+                config.general.terminal.clone(),
+                config.general.args.clone(),
+                &[],
+            );
+            let _ = tomanager.send(search::SearchEvent::Launched(entry.id.clone()));
+        }
+        // Shift+Enter on a file lists the apps able to open it
+        ui::Row::File(file) if secondary => {
+            let _ = tomanager.send(search::SearchEvent::OpenWith {
+                uri: file.uri(),
+                mime_type: file.mime_type(),
+            });
+            return;
+        }
+        ui::Row::Recent(entry) if secondary => {
+            let _ = tomanager.send(search::SearchEvent::OpenWith {
+                uri: entry.uri.clone(),
+                mime_type: entry.mime_type.clone(),
+            });
+            return;
+        }
+        ui::Row::OpenWith(entry, uri) => {
+            entry.launch(
+                config.general.terminal.clone(),
+                config.general.args.clone(),
+                std::slice::from_ref(uri),
+            );
+            let _ = tomanager.send(search::SearchEvent::Launched(entry.id.clone()));
+        }
+        ui::Row::Mathematic(answer) => {
+            let text = if secondary {
+                format!("{} = {}", answer.expr, answer.plain)
+            } else {
+                answer.plain.clone()
+            };
+            ui::copy_to_clipboard(&text, config.calc.copy_to_primary);
+            let _ = tomanager.send(search::SearchEvent::Calculated(answer.clone()));
+        }
+        ui::Row::Url(link) => {
+            link.open(config.general.terminal.clone(), config.general.args.clone());
+        }
+        ui::Row::WebSearch(search) => search.open(),
+        ui::Row::Window(window) => window.focus(),
+        ui::Row::Ssh(host) => {
+            host.connect(config.general.terminal.clone(), config.general.args.clone());
+        }
+        ui::Row::Page(page) => {
+            page.open(config.general.terminal.clone(), config.general.args.clone());
+        }
+        // the `[executables]` list says which ones need a terminal
+        ui::Row::Executable(executable) => {
+            let in_terminal = config.executables.terminal.contains(&executable.name);
+            executable.launch(
+                in_terminal != secondary,
+                &config.general.terminal,
+                &config.general.args,
+            );
+        }
+        // commands run in the terminal, Shift+Enter detaches them
+        ui::Row::Shell(shell) => {
+            shell.run(!secondary, &config.general.terminal, &config.general.args);
+            let _ = tomanager.send(search::SearchEvent::Ran(shell.command.clone()));
+        }
+        ui::Row::File(file) => file.open(),
+        ui::Row::Recent(entry) => entry.open(),
+        ui::Row::Title(_) => return,
+    }
+
+    let _ = tomanager.send(search::SearchEvent::RequestClose);
+}
+
+/// Replaces a typed path or command with the focused row, `Proceed`
+/// otherwise so that Tab still moves the focus.
+fn complete_query(entry: &gtk::Entry, scroll_container: &gtk::ScrolledWindow) -> glib::Propagation {
+    let query = entry.text();
+    let prefix = shell::parse(&query).map(|(prefix, _)| prefix);
+    if !paths::is_path_query(&query) && prefix.is_none() {
+        return glib::Propagation::Proceed;
+    }
+
+    let focused = focused_item(scroll_container).and_then(|(model, position)| model.item(position));
+    if let Some(obj) = focused.and_downcast::<glib::BoxedAnyObject>() {
+        let completion = match (&*obj.borrow::<ui::Row>(), prefix) {
+            (ui::Row::File(file), None) => Some(paths::completion(&query, file)),
+            (ui::Row::Shell(shell), Some(prefix)) => Some(shell::completion(prefix, shell)),
+            _ => None,
+        };
+        if let Some(completion) = completion {
+            entry.set_text(&completion);
+            entry.set_position(-1);
+        }
+    }
+    glib::Propagation::Stop
+}
+
+/// The row Enter applies to: the selected one, or the first activatable one.
+fn focused_item(scroll_container: &gtk::ScrolledWindow) -> Option<(gtk::SelectionModel, u32)> {
+    if !scroll_container.is_visible() {
+        return None;
+    }
+
+    let child = scroll_container.child()?;
+    let model = match child.downcast_ref::<gtk::ListView>() {
+        Some(view) => view.model()?,
+        None => child.downcast_ref::<gtk::GridView>()?.model()?,
+    };
+
+    let selected = model
+        .downcast_ref::<gtk::SingleSelection>()
+        .map(|selection| selection.selected())
+        .unwrap_or(gtk::INVALID_LIST_POSITION);
+    if selected != gtk::INVALID_LIST_POSITION {
+        return Some((model, selected));
+    }
+
+    let position = (0..model.n_items()).find(|i| {
+        model
+            .item(*i)
+            .and_downcast::<glib::BoxedAnyObject>()
+            .is_some_and(|obj| !matches!(*obj.borrow::<ui::Row>(), ui::Row::Title(_)))
+    })?;
+    Some((model, position))
+}
+
 fn load_css(css: String, previous_provider: Option<gtk::CssProvider>) {
     let provider = gtk::CssProvider::new();
     provider.load_from_string(&css);
@@ -260,6 +520,11 @@ fn load_css(css: String, previous_provider: Option<gtk::CssProvider>) {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     if bus::app_is_running() {
         bus::send_represent_event();
     } else {
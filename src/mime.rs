@@ -0,0 +1,317 @@
+use crate::app::AppEntry;
+use gtk::gio;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Desktop file ids along with the index of the list naming them, lower
+/// indices take precedence.
+type Associations = HashMap<String, Vec<(String, usize)>>;
+
+/// The associations of every `mimeapps.list`, the most specific file first.
+#[derive(Default)]
+pub struct MimeApps {
+    defaults: Associations,
+    added: Associations,
+    removed: Associations,
+}
+
+/// Desktop file ids are listed with their extension, app ids are not.
+fn app_id(desktop_id: &str) -> &str {
+    desktop_id.strip_suffix(".desktop").unwrap_or(desktop_id)
+}
+
+fn xdg_dirs(var: &str, fallback: &str) -> Vec<PathBuf> {
+    std::env::var(var)
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or(fallback.to_string())
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// `mimeapps.list` locations in lookup order, desktop specific lists
+/// before the generic ones.
+fn list_paths() -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map_or(PathBuf::from(&home).join(".config"), PathBuf::from);
+    let data_home = std::env::var("XDG_DATA_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map_or(PathBuf::from(&home).join(".local/share"), PathBuf::from);
+
+    let desktops: Vec<String> = std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|desktop| !desktop.is_empty())
+        .map(|desktop| desktop.to_lowercase())
+        .collect();
+
+    let mut config_dirs = vec![config_home];
+    config_dirs.extend(xdg_dirs("XDG_CONFIG_DIRS", "/etc/xdg"));
+    let mut data_dirs = vec![data_home];
+    data_dirs.extend(xdg_dirs("XDG_DATA_DIRS", "/usr/local/share:/usr/share"));
+
+    let mut paths = vec![];
+    for dir in config_dirs
+        .into_iter()
+        .chain(data_dirs.into_iter().map(|dir| dir.join("applications")))
+    {
+        for desktop in &desktops {
+            paths.push(dir.join(format!("{desktop}-mimeapps.list")));
+        }
+        paths.push(dir.join("mimeapps.list"));
+    }
+    paths
+}
+
+impl MimeApps {
+    pub fn load() -> Self {
+        let mut apps = MimeApps::default();
+        for (index, path) in list_paths().into_iter().enumerate() {
+            if let Ok(data) = std::fs::read_to_string(&path) {
+                apps.add_list(index, &path, &data);
+            }
+        }
+        apps
+    }
+
+    /// Adds the associations of the list found at `index` in lookup order.
+    pub fn add_list(&mut self, index: usize, path: &Path, data: &str) {
+        let mut section = String::new();
+        for item in ini_roundtrip::Parser::new(data) {
+            match item {
+                ini_roundtrip::Item::Section { name, .. } => section = name.to_string(),
+                ini_roundtrip::Item::Property {
+                    key,
+                    val: Some(val),
+                    ..
+                } => {
+                    let target = match section.as_str() {
+                        "Default Applications" => &mut self.defaults,
+                        "Added Associations" => &mut self.added,
+                        "Removed Associations" => &mut self.removed,
+                        _ => continue,
+                    };
+                    // earlier lists take precedence, later ones only add
+                    let ids = target.entry(key.trim().to_string()).or_default();
+                    for id in val.split(';').map(str::trim).filter(|id| !id.is_empty()) {
+                        let id = app_id(id);
+                        if !ids.iter().any(|(known, _)| known == id) {
+                            ids.push((id.to_string(), index));
+                        }
+                    }
+                }
+                ini_roundtrip::Item::Error(e) => warn!("{}: {e}", path.display()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether a list removes the association of `id` made by the list at
+    /// `index`, removals only apply to their own list and the later ones.
+    fn is_removed(&self, mime_type: &str, id: &str, index: usize) -> bool {
+        self.removed.get(mime_type).is_some_and(|ids| {
+            ids.iter()
+                .any(|(removed, removed_in)| removed == id && *removed_in <= index)
+        })
+    }
+
+    /// The default app of `mime_type`, the first listed one that is
+    /// installed.
+    pub fn default_app<'a>(
+        &self,
+        mime_type: &str,
+        entries: &'a [AppEntry],
+    ) -> Option<&'a AppEntry> {
+        self.default_with_index(mime_type, entries)
+            .map(|(entry, _)| entry)
+    }
+
+    fn default_with_index<'a>(
+        &self,
+        mime_type: &str,
+        entries: &'a [AppEntry],
+    ) -> Option<(&'a AppEntry, usize)> {
+        self.defaults
+            .get(mime_type)?
+            .iter()
+            .find_map(|(id, index)| {
+                let entry = entries.iter().find(|entry| entry.id == *id)?;
+                Some((entry, *index))
+            })
+    }
+
+    /// Every app able to open `mime_type`, the default one first, then the
+    /// added associations, then the apps declaring the type or one of its
+    /// parents.
+    pub fn handlers(&self, mime_type: &str, entries: &[AppEntry]) -> Vec<AppEntry> {
+        let default = self
+            .default_with_index(mime_type, entries)
+            .map(|(entry, index)| (entry.id.as_str(), index));
+        let added = self
+            .added
+            .get(mime_type)
+            .into_iter()
+            .flatten()
+            .map(|(id, index)| (id.as_str(), *index));
+        let declared = entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .mime_types
+                    .iter()
+                    .any(|declared| declared == mime_type)
+            })
+            .map(|entry| (entry.id.as_str(), usize::MAX));
+        // `text/x-rust` files open in text editors as well
+        let parents = entries
+            .iter()
+            .filter(|entry| {
+                entry.mime_types.iter().any(|declared| {
+                    declared != mime_type && gio::content_type_is_a(mime_type, declared)
+                })
+            })
+            .map(|entry| (entry.id.as_str(), usize::MAX));
+
+        // what desktop entries declare comes after every list
+        let mut ids: Vec<&str> = vec![];
+        for (id, index) in default
+            .into_iter()
+            .chain(added)
+            .chain(declared)
+            .chain(parents)
+        {
+            if !ids.contains(&id) && !self.is_removed(mime_type, id, index) {
+                ids.push(id);
+            }
+        }
+        ids.into_iter()
+            .filter_map(|id| entries.iter().find(|entry| entry.id == id))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, mime_types: &[&str]) -> AppEntry {
+        AppEntry {
+            id: id.to_string(),
+            exec: id.to_string(),
+            need_terminal: false,
+            icon: String::new(),
+            name: id.to_string(),
+            description: String::new(),
+            path: PathBuf::from(format!("/usr/share/applications/{id}.desktop")),
+            mime_types: mime_types.iter().map(|m| m.to_string()).collect(),
+            aliases: vec![],
+            no_display: false,
+        }
+    }
+
+    fn handlers(lists: &[&str], entries: &[AppEntry]) -> Vec<String> {
+        let mut apps = MimeApps::default();
+        for (index, data) in lists.iter().enumerate() {
+            apps.add_list(index, Path::new("mimeapps.list"), data);
+        }
+        apps.handlers("text/plain", entries)
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect()
+    }
+
+    #[test]
+    fn precedence() {
+        let entries = [
+            entry("gedit", &["text/plain"]),
+            entry("kate", &["text/plain"]),
+            entry("vim", &[]),
+            entry("emacs", &[]),
+        ];
+        let user = "\
+[Default Applications]
+text/plain=kate.desktop;
+
+[Added Associations]
+text/plain=vim.desktop;
+";
+        let system = "\
+[Default Applications]
+text/plain=gedit.desktop;
+
+[Added Associations]
+text/plain=emacs.desktop;vim.desktop;
+";
+        assert_eq!(
+            handlers(&[user, system], &entries),
+            ["kate", "vim", "emacs", "gedit"]
+        );
+        assert_eq!(
+            handlers(&[system, user], &entries),
+            ["gedit", "emacs", "vim", "kate"]
+        );
+    }
+
+    #[test]
+    fn removals_apply_to_lower_lists() {
+        let entries = [
+            entry("gedit", &["text/plain"]),
+            entry("vim", &[]),
+            entry("emacs", &[]),
+        ];
+        let user = "\
+[Added Associations]
+text/plain=vim.desktop;
+
+[Removed Associations]
+text/plain=gedit.desktop;emacs.desktop;
+";
+        let system = "\
+[Added Associations]
+text/plain=emacs.desktop;
+
+[Removed Associations]
+text/plain=vim.desktop;
+";
+        // the user keeps vim even though the system list removes it
+        assert_eq!(handlers(&[user, system], &entries), ["vim"]);
+        assert_eq!(handlers(&[system, user], &entries), ["emacs"]);
+    }
+
+    #[test]
+    fn default_app() {
+        // "Open with other application" entries are never listed
+        let userapp = AppEntry {
+            no_display: true,
+            ..entry("userapp-mousepad-X1Y2Z3", &[])
+        };
+        let entries = [entry("gedit", &["text/plain"]), entry("kate", &[]), userapp];
+        let mut apps = MimeApps::default();
+        apps.add_list(
+            0,
+            Path::new("mimeapps.list"),
+            "[Default Applications]\n\
+             text/plain=missing.desktop;kate.desktop\n\
+             text/markdown=userapp-mousepad-X1Y2Z3.desktop\n",
+        );
+        assert_eq!(
+            apps.default_app("text/plain", &entries)
+                .map(|e| e.id.as_str()),
+            Some("kate")
+        );
+        assert_eq!(
+            apps.default_app("text/markdown", &entries)
+                .map(|e| e.id.as_str()),
+            Some("userapp-mousepad-X1Y2Z3")
+        );
+        assert_eq!(apps.default_app("image/png", &entries), None);
+    }
+}
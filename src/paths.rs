@@ -0,0 +1,189 @@
+use crate::files::{expand_home, FileEntry};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::cmp::Reverse;
+use std::io::ErrorKind;
+
+const PATH_LIMIT: usize = 50;
+
+/// A query starting with `/`, `~` or `~/` browses the file system, `~0`
+/// stays a calculation.
+pub fn is_path_query(query: &str) -> bool {
+    query.starts_with('/') || query == "~" || query.starts_with("~/")
+}
+
+/// The typed directory, up to its last `/`, and the partial name after it.
+fn split(query: &str) -> (&str, &str) {
+    match query.rfind('/') {
+        Some(i) => query.split_at(i + 1),
+        // `~` alone lists the home directory
+        None => (query, ""),
+    }
+}
+
+/// The directory being listed, as typed.
+pub fn directory(query: &str) -> &str {
+    split(query).0
+}
+
+/// Children of the typed directory matching the partial name, names it
+/// starts with first. `Err` when the directory can't be read.
+pub fn complete(matcher: &SkimMatcherV2, query: &str) -> Result<Vec<FileEntry>, ErrorKind> {
+    let (dir, partial) = split(query);
+    let read_dir = match std::fs::read_dir(expand_home(dir)) {
+        Ok(read_dir) => read_dir,
+        // the directory is being typed
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.kind()),
+    };
+
+    let lowercase = partial.to_lowercase();
+    let mut scored = vec![];
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // dot files only once the dot is typed
+        if name.starts_with('.') && !partial.starts_with('.') {
+            continue;
+        }
+
+        let is_prefix = name.to_lowercase().starts_with(&lowercase);
+        // a single character is too short for fuzzy matches to mean anything
+        let score = match is_prefix {
+            true => 0,
+            false if partial.chars().count() < 2 => continue,
+            false => match matcher.fuzzy_match(&name, partial) {
+                Some(score) => score,
+                None => continue,
+            },
+        };
+        // symlinks to directories complete as directories
+        let is_dir = entry.path().is_dir();
+        scored.push((
+            !is_prefix,
+            Reverse(score),
+            name.to_lowercase(),
+            FileEntry {
+                path: entry.path(),
+                name,
+                is_dir,
+            },
+        ));
+    }
+
+    scored.sort_by(|a, b| (a.0, a.1, &a.2).cmp(&(b.0, b.1, &b.2)));
+    Ok(scored
+        .into_iter()
+        .take(PATH_LIMIT)
+        .map(|(_, _, _, entry)| entry)
+        .collect())
+}
+
+/// What Tab turns `query` into once `entry` is picked, directories end
+/// with a `/` to keep browsing.
+pub fn completion(query: &str, entry: &FileEntry) -> String {
+    let dir = match split(query).0 {
+        "~" => "~/",
+        dir => dir,
+    };
+    let slash = if entry.is_dir { "/" } else { "" };
+    format!("{dir}{}{slash}", entry.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    fn names(root: &Path, partial: &str) -> Vec<String> {
+        let query = format!("{}/{partial}", root.display());
+        complete(&SkimMatcherV2::default(), &query)
+            .unwrap()
+            .into_iter()
+            .map(|entry| match entry.is_dir {
+                true => format!("{}/", entry.name),
+                false => entry.name,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prefixes_before_fuzzy_matches() {
+        let root = tempfile::tempdir().unwrap();
+        for name in [
+            "Documents",
+            "docker-compose.yml",
+            "my-docs",
+            "dash",
+            ".docs",
+        ] {
+            std::fs::write(root.path().join(name), "").unwrap();
+        }
+        std::fs::create_dir(root.path().join("downloads")).unwrap();
+
+        assert_eq!(
+            names(root.path(), "doc"),
+            ["docker-compose.yml", "Documents", "my-docs"]
+        );
+        // a single character only lists prefixes
+        assert_eq!(
+            names(root.path(), "d"),
+            ["dash", "docker-compose.yml", "Documents", "downloads/"]
+        );
+        assert_eq!(names(root.path(), "").len(), 5);
+    }
+
+    #[test]
+    fn dot_files_once_the_dot_is_typed() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join(".bashrc"), "").unwrap();
+        std::fs::write(root.path().join("bin"), "").unwrap();
+
+        assert_eq!(names(root.path(), ""), ["bin"]);
+        assert_eq!(names(root.path(), "b"), ["bin"]);
+        assert_eq!(names(root.path(), "."), [".bashrc"]);
+    }
+
+    #[test]
+    fn unreadable_directories() {
+        let root = tempfile::tempdir().unwrap();
+        let matcher = SkimMatcherV2::default();
+        // still being typed
+        let missing = format!("{}/missing/", root.path().display());
+        assert_eq!(complete(&matcher, &missing), Ok(vec![]));
+
+        let locked = root.path().join("locked");
+        std::fs::create_dir(&locked).unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        // root reads it anyway
+        if std::fs::read_dir(&locked).is_err() {
+            let query = format!("{}/", locked.display());
+            assert_eq!(complete(&matcher, &query), Err(ErrorKind::PermissionDenied));
+        }
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn completions() {
+        let entry = |name: &str, is_dir| FileEntry {
+            path: Path::new("/home/me").join(name),
+            name: name.to_string(),
+            is_dir,
+        };
+        assert_eq!(completion("~", &entry("src", true)), "~/src/");
+        assert_eq!(completion("~/sr", &entry("src", true)), "~/src/");
+        assert_eq!(completion("/etc/ho", &entry("hosts", false)), "/etc/hosts");
+    }
+
+    #[test]
+    fn path_queries() {
+        assert!(is_path_query("/"));
+        assert!(is_path_query("~"));
+        assert!(is_path_query("~/src"));
+        assert!(!is_path_query("~0"));
+        assert!(!is_path_query("~5 & 3"));
+        assert!(!is_path_query("src/main.rs"));
+        assert_eq!(directory("~/src/ma"), "~/src/");
+        assert_eq!(directory("~"), "~");
+    }
+}
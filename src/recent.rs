@@ -0,0 +1,276 @@
+use crate::app::{self, AppEntry};
+use crate::files;
+use crate::tz;
+use gtk::gio::{self, prelude::*};
+use gtk::glib;
+use std::path::PathBuf;
+use std::process::Command;
+use tracing::{debug, warn};
+
+/// The app a recent file was last opened with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentApp {
+    pub name: String,
+    /// command line, its field codes stand for the file
+    pub exec: String,
+}
+
+impl RecentApp {
+    /// A bare entry running `exec`, to expand its field codes.
+    fn to_entry(&self) -> AppEntry {
+        AppEntry {
+            id: String::new(),
+            exec: self.exec.clone(),
+            need_terminal: false,
+            icon: String::new(),
+            name: self.name.clone(),
+            description: String::new(),
+            path: PathBuf::new(),
+            mime_types: vec![],
+            aliases: vec![],
+            no_display: false,
+        }
+    }
+}
+
+/// A bookmark of `recently-used.xbel`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentEntry {
+    pub uri: String,
+    pub name: String,
+    pub mime_type: String,
+    pub app: Option<RecentApp>,
+    /// unix time of the last visit
+    pub visited: i64,
+}
+
+impl RecentEntry {
+    /// Opens the file with the app that last opened it, or with the default
+    /// handler of its type when none is recorded.
+    pub fn open(&self) {
+        if let Some(app) = &self.app {
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(app.to_entry().expand_exec(std::slice::from_ref(&self.uri)));
+            app::spawn_detached(command);
+            return;
+        }
+
+        if let Err(e) =
+            gio::AppInfo::launch_default_for_uri(&self.uri, None::<&gio::AppLaunchContext>)
+        {
+            warn!("unable to open {}: {e}", self.uri);
+        }
+    }
+
+    /// Where the file is, with `~` standing for the home directory, and the
+    /// app it was opened with.
+    pub fn description(&self) -> String {
+        let location = match glib::filename_from_uri(&self.uri) {
+            Ok((path, _)) => files::abbreviate_home(path.parent().unwrap_or(&path)),
+            Err(_) => self.uri.clone(),
+        };
+        match &self.app {
+            Some(app) => format!("{} · {location}", app.name),
+            None => location,
+        }
+    }
+}
+
+pub fn xbel_path() -> PathBuf {
+    glib::user_data_dir().join("recently-used.xbel")
+}
+
+/// `2024-05-01T10:00:00.123456Z` in unix time.
+fn parse_stamp(stamp: &str) -> Option<i64> {
+    let (date, time) = stamp.trim_end_matches('Z').split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let time = time.split('.').next()?;
+    let mut time = time.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    let days = tz::days_from_civil(year, month as u32, day as u32);
+    Some(days * tz::SECONDS_PER_DAY + hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Reads the recent files, most recently visited first. Local files that
+/// are gone are left out.
+pub fn load() -> Vec<RecentEntry> {
+    let path = xbel_path();
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) => {
+            debug!("{}: {e}", path.display());
+            return vec![];
+        }
+    };
+    match parse(&text) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("{}: {e}", path.display());
+            vec![]
+        }
+    }
+}
+
+fn parse(text: &str) -> Result<Vec<RecentEntry>, roxmltree::Error> {
+    let document = roxmltree::Document::parse(text)?;
+    let mut entries: Vec<RecentEntry> = document
+        .descendants()
+        .filter(|node| node.has_tag_name("bookmark"))
+        .filter_map(parse_bookmark)
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.visited));
+    Ok(entries)
+}
+
+fn parse_bookmark(bookmark: roxmltree::Node) -> Option<RecentEntry> {
+    let uri = bookmark.attribute("href")?.to_string();
+    let local = glib::filename_from_uri(&uri).ok().map(|(path, _)| path);
+    if local.as_ref().is_some_and(|path| !path.exists()) {
+        return None;
+    }
+
+    let name = match &local {
+        Some(path) => path.file_name()?.to_string_lossy().to_string(),
+        None => {
+            let last = uri.trim_end_matches('/').rsplit('/').next()?;
+            glib::Uri::unescape_string(last, None::<&str>)
+                .map_or(last.to_string(), |s| s.to_string())
+        }
+    };
+
+    let visited = ["visited", "modified", "added"]
+        .iter()
+        .filter_map(|attr| parse_stamp(bookmark.attribute(*attr)?))
+        .max()
+        .unwrap_or(0);
+
+    let mime_type = bookmark
+        .descendants()
+        .find(|node| node.has_tag_name("mime-type"))
+        .and_then(|node| node.attribute("type"))
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    // the last app to open the file, its command is shell quoted as a whole
+    let app = bookmark
+        .descendants()
+        .filter(|node| node.has_tag_name("application"))
+        .max_by_key(|node| node.attribute("modified").and_then(parse_stamp))
+        .and_then(|node| {
+            let exec = glib::shell_unquote(node.attribute("exec")?).ok()?;
+            Some(RecentApp {
+                name: node.attribute("name")?.to_string(),
+                exec: exec.to_string_lossy().to_string(),
+            })
+        });
+
+    Some(RecentEntry {
+        uri,
+        name,
+        mime_type,
+        app,
+        visited,
+    })
+}
+
+/// Calls `on_change` whenever `recently-used.xbel` gets written, the
+/// monitor stops once dropped.
+pub fn watch(on_change: impl Fn() + 'static) -> Option<gio::FileMonitor> {
+    let monitor = gio::File::for_path(xbel_path())
+        .monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+        .inspect_err(|e| warn!("unable to watch recent files: {e}"))
+        .ok()?;
+    monitor.connect_changed(move |_, _, _, event| {
+        // the file is replaced as a whole, partial writes are not interesting
+        if matches!(
+            event,
+            gio::FileMonitorEvent::ChangesDoneHint
+                | gio::FileMonitorEvent::Created
+                | gio::FileMonitorEvent::Renamed
+                | gio::FileMonitorEvent::MovedIn
+        ) {
+            on_change();
+        }
+    });
+    Some(monitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps() {
+        assert_eq!(parse_stamp("2024-05-01T10:00:00.123456Z"), Some(1714557600));
+        assert_eq!(parse_stamp("2024-05-01T10:00:00Z"), Some(1714557600));
+        assert_eq!(parse_stamp("2024-13-01T10:00:00Z"), None);
+        assert_eq!(parse_stamp("2024-00-01T10:00:00Z"), None);
+        assert_eq!(parse_stamp("2024-05-32T10:00:00Z"), None);
+        assert_eq!(parse_stamp("2024-05-01"), None);
+    }
+
+    #[test]
+    fn bookmarks() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["notes.txt", "paper.pdf"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let uri = |name: &str| glib::filename_to_uri(dir.path().join(name), None).unwrap();
+        let xbel = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<xbel version="1.0"
+      xmlns:bookmark="http://www.freedesktop.org/standards/desktop-bookmarks"
+      xmlns:mime="http://www.freedesktop.org/standards/shared-mime-info">
+  <bookmark href="{notes}" added="2024-04-30T08:00:00Z" modified="2024-05-01T08:00:00Z" visited="2024-05-02T08:00:00Z">
+    <info><metadata owner="http://freedesktop.org">
+      <mime:mime-type type="text/plain"/>
+      <bookmark:applications>
+        <bookmark:application name="Text Editor" exec="&apos;gedit %u&apos;" modified="2024-05-01T08:00:00Z" count="1"/>
+        <bookmark:application name="Kate" exec="&apos;kate --new %u&apos;" modified="2024-05-02T08:00:00Z" count="1"/>
+        <bookmark:application name="Broken" exec="&apos;broken %u&apos;" modified="2024-13-02T08:00:00Z" count="1"/>
+      </bookmark:applications>
+    </metadata></info>
+  </bookmark>
+  <bookmark href="{gone}" added="2024-05-09T08:00:00Z" modified="2024-05-09T08:00:00Z"/>
+  <bookmark href="{paper}" added="2024-05-05T08:30:00Z" modified="2024-05-05T08:30:00Z"/>
+  <bookmark href="https://example.com/q1/report%20final.pdf" added="2024-05-01T10:00:00Z"/>
+</xbel>"#,
+            notes = uri("notes.txt"),
+            gone = uri("gone.txt"),
+            paper = uri("paper.pdf"),
+        );
+
+        let entries = parse(&xbel).unwrap();
+        let listed: Vec<(&str, i64)> = entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.visited))
+            .collect();
+        // visited last first, files that are gone left out
+        assert_eq!(
+            listed,
+            [
+                ("paper.pdf", 1714897800),
+                ("notes.txt", 1714636800),
+                ("report final.pdf", 1714557600),
+            ]
+        );
+
+        assert_eq!(entries[1].mime_type, "text/plain");
+        assert_eq!(
+            entries[1].app,
+            Some(RecentApp {
+                name: "Kate".to_string(),
+                exec: "kate --new %u".to_string(),
+            })
+        );
+        assert_eq!(entries[0].mime_type, "application/octet-stream");
+        assert_eq!(entries[0].app, None);
+        assert!(parse("<xbel>").is_err());
+    }
+}
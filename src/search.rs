@@ -1,93 +1,1171 @@
 use crate::app;
+use crate::browser::{self, Page};
+use crate::calc;
+use crate::conf::{
+    self, AliasConf, AppsConf, BrowsersConf, CalcConf, Config, EmptyState, ExecutablesConf,
+};
+use crate::dates;
+use crate::executables::{self, Executable, ExecutableIndex};
+use crate::files::{FileEntry, FileIndex};
+use crate::history::{CommandHistory, LaunchHistory};
+use crate::locate::{LocateIndex, Located};
+use crate::mime::MimeApps;
+use crate::paths;
+use crate::recent::{self, RecentEntry};
+use crate::shell::{self, ShellCommand};
+use crate::ssh::{self, SshHost};
+use crate::tz::Zone;
+use crate::units;
+use crate::url::{self, Link};
+use crate::websearch::{self, WebSearch, WebSearchConf};
+use crate::windows::{self, Window};
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+// how many entries are scored between two cancellation checks
+const CANCEL_CHECK_INTERVAL: usize = 64;
+const FREQUENT_APPS_LIMIT: usize = 20;
+const FILES_LIMIT: usize = 8;
+const RECENT_LIMIT: usize = 5;
+const EXECUTABLES_LIMIT: usize = 5;
+const PAGES_LIMIT: usize = 5;
+const SSH_LIMIT: usize = 5;
+const WINDOWS_LIMIT: usize = 5;
+// shorter queries match most of the index
+const MIN_FILE_QUERY_LEN: usize = 2;
+// shorter queries match about every executable, host, page and window
+const MIN_QUERY_LEN: usize = 2;
 
 pub enum SearchEvent {
     Term(String),
     Represent,
+    /// An app got launched, carries its desktop file id.
+    Launched(String),
+    /// A calculator answer got copied, it becomes `ans` and its variables
+    /// are kept right away.
+    Calculated(calc::Answer),
+    /// `recently-used.xbel` got written.
+    RecentChanged,
+    /// A shell command got run, it goes to the command history.
+    Ran(String),
+    /// Lists the apps able to open a file instead of the results.
+    OpenWith {
+        uri: String,
+        mime_type: String,
+    },
     RequestClose,
 }
 
+pub enum Section {
+    Mathematic(calc::Answer),
+    Apps(Vec<app::AppEntry>),
+    /// Open windows to switch to.
+    Windows(Vec<Window>),
+    /// Programs of `$PATH` no app runs.
+    Executables(Vec<Executable>),
+    Files(Vec<FileEntry>),
+    Recent(Vec<RecentEntry>),
+    /// Hosts to open an ssh session with.
+    Ssh(Vec<SshHost>),
+    /// Browser bookmarks and history.
+    Pages(Vec<Page>),
+    WebSearch(WebSearch),
+    /// Suggestions for a `> command` query.
+    Shell(Vec<ShellCommand>),
+    /// A url typed in the search box, shown first.
+    Url(Link),
+    /// Children of the typed directory `dir`.
+    Path {
+        dir: String,
+        entries: Result<Vec<FileEntry>, std::io::ErrorKind>,
+    },
+    /// Apps able to open `uri`, the default one first.
+    OpenWith {
+        uri: String,
+        apps: Vec<app::AppEntry>,
+    },
+    /// Apps shown while the query is empty.
+    Drawer(Vec<app::AppEntry>),
+}
+
 pub enum ManagerEvent {
-    DisplayEntries(Vec<app::AppEntry>),
-    Mathematic(f64),
-    Clear,
+    /// The complete result set of a query, it replaces whatever is displayed.
+    Results {
+        query_id: u64,
+        sections: Vec<Section>,
+    },
     Close,
 }
 
+/// A provider reloaded in the background, it replaces the previous snapshot.
+enum Reloaded {
+    Apps(Vec<app::AppEntry>),
+    Recent(Vec<RecentEntry>),
+    Pages(Vec<Page>),
+    SshHosts(Vec<SshHost>),
+    Windows(Vec<Window>),
+    Executables(Arc<Vec<Executable>>),
+    LocalZone(Zone),
+    MimeApps(MimeApps),
+}
+
+/// Snapshots of the providers, each replaced as a whole once reloaded.
+#[derive(Clone, Default)]
+struct Sources {
+    files: Arc<Vec<FileEntry>>,
+    located: Arc<Vec<Located>>,
+    recent: Arc<Vec<RecentEntry>>,
+    pages: Arc<Vec<Page>>,
+    ssh_hosts: Arc<Vec<SshHost>>,
+    windows: Arc<Vec<Window>>,
+    executables: Arc<Vec<Executable>>,
+    /// programs the apps start, left out of the executables
+    app_programs: Arc<HashSet<String>>,
+    commands: Arc<CommandHistory>,
+    /// the zone dates are displayed in
+    local_zone: Arc<Zone>,
+    mime_apps: Arc<MimeApps>,
+}
+
+/// The config a query is searched with.
+#[derive(Clone)]
+struct SearchConf {
+    filter: Arc<app::AppFilter>,
+    calc: Arc<CalcConf>,
+    /// variables and functions of the calculator, `ans` included
+    calc_session: Arc<calc::Session>,
+    websearch: Arc<WebSearchConf>,
+    executables: Arc<ExecutablesConf>,
+}
+
+impl SearchConf {
+    fn new(config: &Config) -> Self {
+        Self {
+            filter: Arc::new(app::AppFilter::new(&config.apps)),
+            calc: Arc::new(config.calc.clone()),
+            calc_session: Arc::new(calc::Session::new(&config.calc)),
+            websearch: Arc::new(config.websearch.clone()),
+            executables: Arc::new(config.executables.clone()),
+        }
+    }
+}
+
 pub struct SearchManager {
-    rx: Receiver<SearchEvent>,
+    rx: UnboundedReceiver<SearchEvent>,
     outsender: async_channel::Sender<ManagerEvent>,
-    matcher: SkimMatcherV2,
-    entries: Vec<app::AppEntry>,
+    matcher: Arc<SkimMatcherV2>,
+    entries: Arc<Vec<app::AppEntry>>,
+    apps: AppsConf,
+    aliases: Vec<AliasConf>,
+    conf: SearchConf,
+    history: LaunchHistory,
+    /// The query searched last, and the last answer shown while it got typed.
+    /// The answer becomes `ans` once another query starts.
+    typed: (String, Option<calc::Answer>),
+    sources: Sources,
+    recent_enabled: bool,
+    browsers: BrowsersConf,
+    ssh_enabled: bool,
+    windows_enabled: bool,
+    file_index: Arc<Mutex<FileIndex>>,
+    locate_index: Arc<Mutex<LocateIndex>>,
+    executable_index: Arc<Mutex<ExecutableIndex>>,
+    /// Id of the most recent query, in-flight searches stop as soon as it moves.
+    latest_query: Arc<AtomicU64>,
 }
 
 impl SearchManager {
-    pub fn new() -> (
+    pub fn new(
+        config: &Config,
+    ) -> (
         Self,
-        (Sender<SearchEvent>, async_channel::Receiver<ManagerEvent>),
+        (
+            UnboundedSender<SearchEvent>,
+            async_channel::Receiver<ManagerEvent>,
+        ),
     ) {
-        let (insender, rx) = mpsc::channel::<SearchEvent>();
-        let (outsender, outrx) = async_channel::bounded::<ManagerEvent>(1);
+        let (insender, rx) = mpsc::unbounded_channel::<SearchEvent>();
+        let (outsender, outrx) = async_channel::unbounded::<ManagerEvent>();
+        let entries = app::collect_apps(&app::AppFilter::new(&config.apps), &config.aliases);
         (
-            Self {
-                rx,
-                outsender,
-                matcher: SkimMatcherV2::default(),
-                entries: app::collect_apps(),
-            },
+            Self::with_channels(config, entries, (rx, outsender)),
             (insender, outrx),
         )
     }
 
+    /// A manager searching `entries`, driven through `rx`.
+    fn with_channels(
+        config: &Config,
+        entries: Vec<app::AppEntry>,
+        (rx, outsender): (
+            UnboundedReceiver<SearchEvent>,
+            async_channel::Sender<ManagerEvent>,
+        ),
+    ) -> Self {
+        let app_programs = Arc::new(executables::app_programs(&entries));
+        Self {
+            rx,
+            outsender,
+            matcher: Arc::new(SkimMatcherV2::default()),
+            entries: Arc::new(entries),
+            apps: config.apps.clone(),
+            aliases: config.aliases.clone(),
+            conf: SearchConf::new(config),
+            history: LaunchHistory::load(),
+            typed: Default::default(),
+            sources: Sources {
+                app_programs,
+                commands: Arc::new(CommandHistory::load()),
+                ..Default::default()
+            },
+            recent_enabled: config.recent.enabled,
+            browsers: config.browsers.clone(),
+            ssh_enabled: config.ssh.enabled,
+            windows_enabled: config.windows.enabled,
+            file_index: Arc::new(Mutex::new(FileIndex::new(&config.files))),
+            locate_index: Arc::new(Mutex::new(LocateIndex::new(&config.locate))),
+            executable_index: Arc::new(Mutex::new(ExecutableIndex::default())),
+            latest_query: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
     pub fn manage(mut self) {
         tokio::spawn(async move {
-            while let Ok(ev) = self.rx.recv() {
-                match ev {
-                    SearchEvent::Term(query) => {
-                        let _ = self.outsender.send(ManagerEvent::Clear).await;
-                        let entry_results: Vec<app::AppEntry> = self
-                            .entries
-                            .iter()
-                            .filter_map(|entry| {
-                                let entry = entry.clone();
-                                let score =
-                                    self.matcher.fuzzy_match(&entry.name, &query).unwrap_or(0);
-
-                                if score > 0 {
-                                    Some(entry)
-                                } else {
-                                    None
+            // finished searches come back here so that only the manager decides
+            // what reaches the ui, results of an outdated query are dropped
+            let (donesender, mut donerx) = mpsc::unbounded_channel::<(u64, Vec<Section>)>();
+            let (indexsender, mut indexrx) = mpsc::unbounded_channel::<Vec<FileEntry>>();
+            let (locatesender, mut locaterx) = mpsc::unbounded_channel::<Arc<Vec<Located>>>();
+            let (reloadsender, mut reloadrx) = mpsc::unbounded_channel::<Reloaded>();
+            self.refresh_files(&indexsender, None);
+            self.refresh_locate(&locatesender, None);
+            self.reload_sources(&reloadsender);
+
+            loop {
+                tokio::select! {
+                    // pending events first, a query typed meanwhile outdates
+                    // the finished searches
+                    biased;
+
+                    ev = self.rx.recv() => {
+                        let Some(ev) = ev else {
+                            break;
+                        };
+
+                        match ev {
+                            SearchEvent::Term(query) => {
+                                if !is_edit_of(&self.typed.0, &query) {
+                                    self.remember_answer();
                                 }
-                            })
-                            .collect();
-                        if let Ok(res) = exmex::eval_str::<f64>(&query) {
-                            let _ = self.outsender.send(ManagerEvent::Mathematic(res)).await;
-
-                            let top_1 = &entry_results[..1.min(entry_results.len())];
-                            if !top_1.is_empty() {
-                                let _ = self
-                                    .outsender
-                                    .send(ManagerEvent::DisplayEntries(top_1.to_vec()))
-                                    .await;
+                                self.typed.0 = query;
+                                self.search_typed(&donesender);
+                            }
+                            SearchEvent::Represent => {
+                                self.remember_answer();
+                                // the config may have been edited, from the cli for instance
+                                let config = Config::get_conf(&conf::init_config_dir());
+                                // the calculator keeps its variables
+                                let mut calc_session = self.conf.calc_session.clone();
+                                Arc::make_mut(&mut calc_session).configure(&config.calc);
+                                self.conf = SearchConf {
+                                    calc_session,
+                                    ..SearchConf::new(&config)
+                                };
+                                self.apps = config.apps;
+                                self.aliases = config.aliases;
+                                self.refresh_files(&indexsender, Some(config.files));
+                                self.refresh_locate(&locatesender, Some(config.locate));
+                                self.recent_enabled = config.recent.enabled;
+                                self.browsers = config.browsers;
+                                self.ssh_enabled = config.ssh.enabled;
+                                self.windows_enabled = config.windows.enabled;
+                                self.reload_sources(&reloadsender);
+                                self.reload_apps(&reloadsender);
+                            }
+                            SearchEvent::Launched(id) => self.history.record(&id),
+                            SearchEvent::Calculated(answer) => {
+                                self.typed.1 = None;
+                                Arc::make_mut(&mut self.conf.calc_session).remember(&answer);
+                            }
+                            SearchEvent::RecentChanged => self.reload_recent(&reloadsender),
+                            SearchEvent::Ran(command) => {
+                                Arc::make_mut(&mut self.sources.commands).record(&command);
+                            }
+                            SearchEvent::OpenWith { uri, mime_type } => {
+                                let query_id = self.latest_query.fetch_add(1, Ordering::SeqCst) + 1;
+                                let entries = self.entries.clone();
+                                let mime_apps = self.sources.mime_apps.clone();
+                                let donesender = donesender.clone();
+                                tokio::task::spawn_blocking(move || {
+                                    let apps = mime_apps.handlers(&mime_type, &entries);
+                                    let sections = vec![Section::OpenWith { uri, apps }];
+                                    let _ = donesender.send((query_id, sections));
+                                });
                             }
-                        } else {
-                            let top_5 = &entry_results[..10.min(entry_results.len())];
-                            if !top_5.is_empty() {
-                                let _ = self
-                                    .outsender
-                                    .send(ManagerEvent::DisplayEntries(top_5.to_vec()))
-                                    .await;
+                            SearchEvent::RequestClose => {
+                                let _ = self.outsender.send(ManagerEvent::Close).await;
                             }
                         }
                     }
-                    SearchEvent::Represent => self.entries = app::collect_apps(),
-                    SearchEvent::RequestClose => {
-                        let _ = self.outsender.send(ManagerEvent::Close).await;
+                    Some(files) = indexrx.recv() => self.sources.files = Arc::new(files),
+                    Some(located) = locaterx.recv() => self.sources.located = located,
+                    Some(reloaded) = reloadrx.recv() => {
+                        // apps pinned or hidden meanwhile show up in the
+                        // displayed results
+                        let apps = matches!(reloaded, Reloaded::Apps(_));
+                        self.apply(reloaded);
+                        if apps {
+                            self.search_typed(&donesender);
+                        }
+                    }
+                    Some((query_id, sections)) = donerx.recv() => {
+                        if query_id != self.latest_query.load(Ordering::SeqCst) {
+                            continue;
+                        }
+
+                        let answer = sections.iter().find_map(|section| match section {
+                            Section::Mathematic(answer) => Some(answer),
+                            _ => None,
+                        });
+                        if let Some(answer) = answer {
+                            self.typed.1 = Some(answer.clone());
+                        }
+
+                        let _ = self
+                            .outsender
+                            .send(ManagerEvent::Results { query_id, sections })
+                            .await;
                     }
                 }
             }
         });
     }
 }
+
+/// Whether `query` got typed from `previous`, by adding or removing
+/// characters at its end.
+fn is_edit_of(previous: &str, query: &str) -> bool {
+    let (previous, query) = (previous.trim(), query.trim());
+    !previous.is_empty()
+        && !query.is_empty()
+        && (query.starts_with(previous) || previous.starts_with(query))
+}
+
+impl SearchManager {
+    /// Keeps the answer of the previous query as `ans`, along with its
+    /// variables. The query being typed never sees its own answer.
+    fn remember_answer(&mut self) {
+        if let Some(answer) = self.typed.1.take() {
+            Arc::make_mut(&mut self.conf.calc_session).remember(&answer);
+        }
+    }
+
+    /// Searches the query typed last in the background, the sections are
+    /// sent on `donesender` along with the id of the query.
+    fn search_typed(&self, donesender: &UnboundedSender<(u64, Vec<Section>)>) {
+        let query_id = self.latest_query.fetch_add(1, Ordering::SeqCst) + 1;
+        let query = self.typed.0.clone();
+        if query.trim().is_empty() {
+            let _ = donesender.send((query_id, self.empty_state()));
+            return;
+        }
+
+        let matcher = self.matcher.clone();
+        let entries = self.entries.clone();
+        let sources = self.sources.clone();
+        let conf = self.conf.clone();
+        let latest_query = self.latest_query.clone();
+        let donesender = donesender.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let is_cancelled = || latest_query.load(Ordering::SeqCst) != query_id;
+            if let Some(sections) =
+                search(&matcher, &entries, &sources, &conf, &query, is_cancelled)
+            {
+                let _ = donesender.send((query_id, sections));
+            }
+        });
+    }
+
+    /// Refreshes the file index in the background, from scratch when `conf`
+    /// differs from the one it was built with.
+    fn refresh_files(
+        &self,
+        indexsender: &UnboundedSender<Vec<FileEntry>>,
+        conf: Option<conf::FilesConf>,
+    ) {
+        let file_index = self.file_index.clone();
+        let indexsender = indexsender.clone();
+        tokio::task::spawn_blocking(move || {
+            let Ok(mut index) = file_index.lock() else {
+                return;
+            };
+            if let Some(conf) = conf.filter(|conf| conf != index.conf()) {
+                *index = FileIndex::new(&conf);
+            }
+            let _ = indexsender.send(index.refresh());
+        });
+    }
+
+    /// Reads the locate database again if it changed, or if `conf` differs
+    /// from the one in use.
+    fn refresh_locate(
+        &self,
+        locatesender: &UnboundedSender<Arc<Vec<Located>>>,
+        conf: Option<conf::LocateConf>,
+    ) {
+        let locate_index = self.locate_index.clone();
+        let locatesender = locatesender.clone();
+        tokio::task::spawn_blocking(move || {
+            let Ok(mut index) = locate_index.lock() else {
+                return;
+            };
+            if let Some(conf) = conf.filter(|conf| conf != index.conf()) {
+                *index = LocateIndex::new(&conf);
+            }
+            let _ = locatesender.send(index.refresh());
+        });
+    }
+
+    /// Reloads every provider but the apps and the file indexes.
+    fn reload_sources(&mut self, reloadsender: &UnboundedSender<Reloaded>) {
+        self.reload_recent(reloadsender);
+        self.reload_pages(reloadsender);
+        self.reload_ssh_hosts(reloadsender);
+        self.reload_windows(reloadsender);
+        self.reload_executables(reloadsender);
+        self.reload_local_zone(reloadsender);
+        self.reload_mime_apps(reloadsender);
+    }
+
+    /// Collects the desktop entries again with the current filter and aliases.
+    fn reload_apps(&self, reloadsender: &UnboundedSender<Reloaded>) {
+        let filter = self.conf.filter.clone();
+        let aliases = self.aliases.clone();
+        let reloadsender = reloadsender.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = reloadsender.send(Reloaded::Apps(app::collect_apps(&filter, &aliases)));
+        });
+    }
+
+    fn reload_recent(&mut self, reloadsender: &UnboundedSender<Reloaded>) {
+        if !self.recent_enabled {
+            self.sources.recent = Arc::new(vec![]);
+            return;
+        }
+        let reloadsender = reloadsender.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = reloadsender.send(Reloaded::Recent(recent::load()));
+        });
+    }
+
+    fn reload_pages(&mut self, reloadsender: &UnboundedSender<Reloaded>) {
+        if !self.browsers.enabled {
+            self.sources.pages = Arc::new(vec![]);
+            return;
+        }
+        let conf = self.browsers.clone();
+        let reloadsender = reloadsender.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = reloadsender.send(Reloaded::Pages(browser::load(&conf)));
+        });
+    }
+
+    fn reload_ssh_hosts(&mut self, reloadsender: &UnboundedSender<Reloaded>) {
+        if !self.ssh_enabled {
+            self.sources.ssh_hosts = Arc::new(vec![]);
+            return;
+        }
+        let reloadsender = reloadsender.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = reloadsender.send(Reloaded::SshHosts(ssh::load()));
+        });
+    }
+
+    /// Windows come and go while seekr is hidden, they are listed again
+    /// each time it shows up.
+    fn reload_windows(&mut self, reloadsender: &UnboundedSender<Reloaded>) {
+        if !self.windows_enabled {
+            self.sources.windows = Arc::new(vec![]);
+            return;
+        }
+        let reloadsender = reloadsender.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = reloadsender.send(Reloaded::Windows(windows::load()));
+        });
+    }
+
+    /// Scans `$PATH` again if one of its directories changed.
+    fn reload_executables(&self, reloadsender: &UnboundedSender<Reloaded>) {
+        let executable_index = self.executable_index.clone();
+        let reloadsender = reloadsender.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Ok(mut index) = executable_index.lock() {
+                let _ = reloadsender.send(Reloaded::Executables(index.refresh()));
+            }
+        });
+    }
+
+    /// Reads the zone of the system again, `/etc/localtime` may have changed
+    /// since seekr started.
+    fn reload_local_zone(&self, reloadsender: &UnboundedSender<Reloaded>) {
+        let reloadsender = reloadsender.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = reloadsender.send(Reloaded::LocalZone(Zone::local()));
+        });
+    }
+
+    /// Reads the `mimeapps.list` files again, default apps may have changed.
+    fn reload_mime_apps(&self, reloadsender: &UnboundedSender<Reloaded>) {
+        let reloadsender = reloadsender.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = reloadsender.send(Reloaded::MimeApps(MimeApps::load()));
+        });
+    }
+
+    /// Swaps in a reloaded provider, unless it got disabled meanwhile.
+    fn apply(&mut self, reloaded: Reloaded) {
+        match reloaded {
+            Reloaded::Apps(entries) => {
+                self.sources.app_programs = Arc::new(executables::app_programs(&entries));
+                self.entries = Arc::new(entries);
+            }
+            Reloaded::Recent(entries) if self.recent_enabled => {
+                self.sources.recent = Arc::new(entries);
+            }
+            Reloaded::Pages(pages) if self.browsers.enabled => {
+                self.sources.pages = Arc::new(pages);
+            }
+            Reloaded::SshHosts(hosts) if self.ssh_enabled => {
+                self.sources.ssh_hosts = Arc::new(hosts);
+            }
+            Reloaded::Windows(windows) if self.windows_enabled => {
+                self.sources.windows = Arc::new(windows);
+            }
+            Reloaded::Executables(executables) => self.sources.executables = executables,
+            Reloaded::LocalZone(zone) => self.sources.local_zone = Arc::new(zone),
+            Reloaded::MimeApps(mime_apps) => self.sources.mime_apps = Arc::new(mime_apps),
+            _ => {}
+        }
+    }
+
+    fn empty_state(&self) -> Vec<Section> {
+        let by_name = |a: &&app::AppEntry, b: &&app::AppEntry| {
+            a.name.to_lowercase().cmp(&b.name.to_lowercase())
+        };
+
+        let listed = || self.entries.iter().filter(|entry| !entry.no_display);
+        let mut apps: Vec<&app::AppEntry> = listed().collect();
+        match self.apps.empty_state {
+            EmptyState::None => return vec![],
+            EmptyState::All => apps.sort_by(by_name),
+            EmptyState::Frequent => {
+                apps.retain(|entry| self.history.count(&entry.id) > 0);
+                apps.sort_by(|a, b| {
+                    let (a_count, b_count) = (self.history.count(&a.id), self.history.count(&b.id));
+                    b_count.cmp(&a_count).then_with(|| by_name(a, b))
+                });
+                apps.truncate(FREQUENT_APPS_LIMIT);
+
+                // nothing got launched yet, fall back to every app
+                if apps.is_empty() {
+                    apps = listed().collect();
+                    apps.sort_by(by_name);
+                }
+            }
+            EmptyState::Pinned => {
+                apps.retain(|entry| self.conf.filter.pinned_rank(entry).is_some());
+                apps.sort_by(|a, b| {
+                    self.conf
+                        .filter
+                        .pinned_rank(a)
+                        .cmp(&self.conf.filter.pinned_rank(b))
+                        .then_with(|| by_name(a, b))
+                });
+            }
+        }
+
+        if apps.is_empty() {
+            return vec![];
+        }
+
+        vec![Section::Drawer(apps.into_iter().cloned().collect())]
+    }
+}
+
+/// Runs `query` against `entries`, returns `None` when the search got
+/// cancelled by a newer query before completing.
+fn search(
+    matcher: &SkimMatcherV2,
+    entries: &[app::AppEntry],
+    sources: &Sources,
+    conf: &SearchConf,
+    query: &str,
+    is_cancelled: impl Fn() -> bool,
+) -> Option<Vec<Section>> {
+    if paths::is_path_query(query) {
+        return Some(vec![Section::Path {
+            dir: paths::directory(query).to_string(),
+            entries: paths::complete(matcher, query),
+        }]);
+    }
+
+    if let Some((_, command)) = shell::parse(query) {
+        let commands = shell::suggest(command, &sources.executables, &sources.commands);
+        return Some(match commands.is_empty() {
+            true => vec![],
+            false => vec![Section::Shell(commands)],
+        });
+    }
+
+    let mut sections = vec![];
+
+    let mut scored: Vec<(bool, usize, Reverse<i64>, &app::AppEntry)> = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        if i % CANCEL_CHECK_INTERVAL == 0 && is_cancelled() {
+            return None;
+        }
+        if entry.no_display {
+            continue;
+        }
+
+        let score = std::iter::once(&entry.name)
+            .chain(&entry.aliases)
+            .filter_map(|candidate| matcher.fuzzy_match(candidate, query))
+            .max()
+            .unwrap_or(0);
+        if score > 0 {
+            // exact aliases first, then pinned apps in the configured order,
+            // then the best matches
+            let exact_alias = entry
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(query.trim()));
+            let rank = conf.filter.pinned_rank(entry).unwrap_or(usize::MAX);
+            scored.push((!exact_alias, rank, Reverse(score), entry));
+        }
+    }
+
+    if is_cancelled() {
+        return None;
+    }
+
+    scored.sort_by_key(|(not_exact, rank, score, _)| (*not_exact, *rank, *score));
+    let entry_results: Vec<app::AppEntry> = scored
+        .into_iter()
+        .map(|(_, _, _, entry)| entry.clone())
+        .collect();
+
+    let calc = &conf.calc;
+    let answer = conf
+        .calc_session
+        .evaluate(query, calc)
+        .or_else(|| units::convert(query, calc))
+        .or_else(|| dates::evaluate(query, &sources.local_zone, calc));
+    if let Some(answer) = answer {
+        sections.push(Section::Mathematic(answer));
+
+        let top_1 = &entry_results[..1.min(entry_results.len())];
+        if !top_1.is_empty() {
+            sections.push(Section::Apps(top_1.to_vec()));
+        }
+    } else {
+        let top_5 = &entry_results[..10.min(entry_results.len())];
+        if !top_5.is_empty() {
+            sections.push(Section::Apps(top_5.to_vec()));
+        }
+
+        let window_results = search_windows(matcher, entries, &sources.windows, query);
+        if !window_results.is_empty() {
+            sections.push(Section::Windows(window_results));
+        }
+
+        if conf.executables.enabled {
+            let executable_results = search_executables(matcher, sources, query, &is_cancelled)?;
+            if !executable_results.is_empty() {
+                sections.push(Section::Executables(executable_results));
+            }
+        }
+
+        let ssh_results = search_ssh(matcher, &sources.ssh_hosts, query);
+        if !ssh_results.is_empty() {
+            sections.push(Section::Ssh(ssh_results));
+        }
+
+        let recent_results = search_recent(matcher, &sources.recent, query, &is_cancelled)?;
+        if !recent_results.is_empty() {
+            sections.push(Section::Recent(recent_results));
+        }
+
+        let page_results = search_pages(matcher, entries, &sources.pages, query, &is_cancelled)?;
+        if !page_results.is_empty() {
+            sections.push(Section::Pages(page_results));
+        }
+
+        let file_results = search_files(
+            matcher,
+            (&sources.files, &sources.located),
+            query,
+            &is_cancelled,
+        )?;
+        if !file_results.is_empty() {
+            sections.push(Section::Files(file_results));
+        }
+    }
+
+    // an explicit keyword comes first, the fallback only when nothing matched
+    if let Some(search) = websearch::detect(query, &conf.websearch) {
+        sections.insert(0, Section::WebSearch(search));
+    } else if sections.is_empty() {
+        if let Some(search) = conf.websearch.fallback(query) {
+            sections.push(Section::WebSearch(search));
+        }
+    }
+
+    // urls would not match anything else
+    if let Some(mut link) = url::detect(query) {
+        link.handler = sources
+            .mime_apps
+            .default_app(&link.mime_type(), entries)
+            .cloned();
+        sections.insert(0, Section::Url(link));
+    }
+
+    Some(sections)
+}
+
+/// Executables matching `query` that no app already starts, shorter names
+/// first on equal scores.
+fn search_executables(
+    matcher: &SkimMatcherV2,
+    sources: &Sources,
+    query: &str,
+    is_cancelled: impl Fn() -> bool,
+) -> Option<Vec<Executable>> {
+    let query = query.trim();
+    if query.chars().count() < MIN_QUERY_LEN {
+        return Some(vec![]);
+    }
+
+    let mut scored = vec![];
+    for (i, executable) in sources.executables.iter().enumerate() {
+        if i % CANCEL_CHECK_INTERVAL == 0 && is_cancelled() {
+            return None;
+        }
+        if sources.app_programs.contains(executable.name.as_str()) {
+            continue;
+        }
+        if let Some(score) = matcher.fuzzy_match(&executable.name, query) {
+            scored.push((Reverse(score), executable.name.len(), executable));
+        }
+    }
+
+    scored.sort_by_key(|(score, len, _)| (*score, *len));
+    Some(
+        scored
+            .into_iter()
+            .take(EXECUTABLES_LIMIT)
+            .map(|(_, _, executable)| executable.clone())
+            .collect(),
+    )
+}
+
+/// Windows whose title or app id match `query`, the most recently focused
+/// first on equal scores.
+fn search_windows(
+    matcher: &SkimMatcherV2,
+    entries: &[app::AppEntry],
+    windows: &[Window],
+    query: &str,
+) -> Vec<Window> {
+    let query = query.trim();
+    if query.chars().count() < MIN_QUERY_LEN {
+        return vec![];
+    }
+
+    let mut scored: Vec<(Reverse<i64>, &Window)> = windows
+        .iter()
+        .filter_map(|window| {
+            let score = [&window.title, &window.app_id]
+                .into_iter()
+                .filter_map(|candidate| matcher.fuzzy_match(candidate, query))
+                .max()?;
+            Some((Reverse(score), window))
+        })
+        .collect();
+    scored.sort_by_key(|(score, _)| *score);
+    scored
+        .into_iter()
+        .take(WINDOWS_LIMIT)
+        .map(|(_, window)| Window {
+            icon: window.app(entries).map(|app| app.icon.clone()),
+            ..window.clone()
+        })
+        .collect()
+}
+
+/// Hosts whose alias or host name match `query`, `ssh web` looks for `web`.
+fn search_ssh(matcher: &SkimMatcherV2, hosts: &[SshHost], query: &str) -> Vec<SshHost> {
+    let query = query.trim();
+    let query = query.strip_prefix("ssh ").map_or(query, str::trim_start);
+    if query.chars().count() < MIN_QUERY_LEN {
+        return vec![];
+    }
+
+    let mut scored: Vec<(Reverse<i64>, &SshHost)> = hosts
+        .iter()
+        .filter_map(|host| {
+            let score = std::iter::once(&host.name)
+                .chain(&host.hostname)
+                .filter_map(|candidate| matcher.fuzzy_match(candidate, query))
+                .max()?;
+            Some((Reverse(score), host))
+        })
+        .collect();
+    // configured hosts come first, the sort keeps them there on equal scores
+    scored.sort_by_key(|(score, _)| *score);
+    scored
+        .into_iter()
+        .take(SSH_LIMIT)
+        .map(|(_, host)| host.clone())
+        .collect()
+}
+
+/// Pages whose title or url match `query`, bookmarks and the most visited
+/// ones first on equal scores.
+fn search_pages(
+    matcher: &SkimMatcherV2,
+    entries: &[app::AppEntry],
+    pages: &[Page],
+    query: &str,
+    is_cancelled: impl Fn() -> bool,
+) -> Option<Vec<Page>> {
+    let query = query.trim();
+    if query.chars().count() < MIN_QUERY_LEN {
+        return Some(vec![]);
+    }
+
+    let mut scored = vec![];
+    for (i, page) in pages.iter().enumerate() {
+        if i % CANCEL_CHECK_INTERVAL == 0 && is_cancelled() {
+            return None;
+        }
+        let score = [&page.title, &page.url]
+            .into_iter()
+            .filter_map(|candidate| matcher.fuzzy_match(candidate, query))
+            .max();
+        if let Some(score) = score {
+            scored.push((Reverse(score), !page.bookmarked, Reverse(page.visits), page));
+        }
+    }
+
+    scored.sort_by_key(|(score, not_bookmarked, visits, _)| (*score, *not_bookmarked, *visits));
+    Some(
+        scored
+            .into_iter()
+            .take(PAGES_LIMIT)
+            .map(|(_, _, _, page)| Page {
+                handler: page.browser.app(entries).cloned(),
+                ..page.clone()
+            })
+            .collect(),
+    )
+}
+
+/// Recent files matching `query`, most recently visited first.
+fn search_recent(
+    matcher: &SkimMatcherV2,
+    recent: &[RecentEntry],
+    query: &str,
+    is_cancelled: impl Fn() -> bool,
+) -> Option<Vec<RecentEntry>> {
+    let query = query.trim();
+    if query.chars().count() < MIN_FILE_QUERY_LEN {
+        return Some(vec![]);
+    }
+
+    let mut results = vec![];
+    // entries are already sorted by visit time
+    for (i, entry) in recent.iter().enumerate() {
+        if i % CANCEL_CHECK_INTERVAL == 0 && is_cancelled() {
+            return None;
+        }
+        if matcher.fuzzy_match(&entry.name, query).is_some() {
+            results.push(entry.clone());
+            if results.len() == RECENT_LIMIT {
+                break;
+            }
+        }
+    }
+    Some(results)
+}
+
+/// Best file matches of the index and the locate database, closer to the
+/// root first on equal scores. Queries with a `/` match whole paths.
+fn search_files(
+    matcher: &SkimMatcherV2,
+    (files, located): (&[FileEntry], &[Located]),
+    query: &str,
+    is_cancelled: impl Fn() -> bool,
+) -> Option<Vec<FileEntry>> {
+    let query = query.trim();
+    if query.chars().count() < MIN_FILE_QUERY_LEN {
+        return Some(vec![]);
+    }
+    let by_path = query.contains('/');
+
+    let indexed = top_files(
+        files,
+        |file| {
+            let score = if by_path {
+                matcher.fuzzy_match(&file.path.to_string_lossy(), query)
+            } else {
+                matcher.fuzzy_match(&file.name, query)
+            };
+            Some((score?, file.path.components().count()))
+        },
+        &is_cancelled,
+    )?;
+    let located = top_files(
+        located,
+        |entry| {
+            let candidate = if by_path { &entry.path } else { entry.name() };
+            let score = matcher.fuzzy_match(candidate, query)?;
+            Some((score, Path::new(&*entry.path).components().count()))
+        },
+        &is_cancelled,
+    )?;
+
+    let mut scored: Vec<(Reverse<i64>, usize, FileEntry)> = indexed
+        .into_iter()
+        .map(|(score, depth, file)| (score, depth, file.clone()))
+        .chain(
+            located
+                .into_iter()
+                .map(|(score, depth, entry)| (score, depth, entry.to_entry())),
+        )
+        .collect();
+    scored.sort_by_key(|(score, depth, _)| (*score, *depth));
+
+    // the database usually lists the indexed files too
+    let mut seen = HashSet::new();
+    Some(
+        scored
+            .into_iter()
+            .map(|(_, _, file)| file)
+            .filter(|file| seen.insert(file.path.clone()))
+            .take(FILES_LIMIT)
+            .collect(),
+    )
+}
+
+/// The `FILES_LIMIT` best scored items, `None` when cancelled.
+fn top_files<T>(
+    items: &[T],
+    score: impl Fn(&T) -> Option<(i64, usize)>,
+    is_cancelled: impl Fn() -> bool,
+) -> Option<Vec<(Reverse<i64>, usize, &T)>> {
+    let mut scored = vec![];
+    for (i, item) in items.iter().enumerate() {
+        if i % CANCEL_CHECK_INTERVAL == 0 && is_cancelled() {
+            return None;
+        }
+        if let Some((score, depth)) = score(item) {
+            scored.push((Reverse(score), depth, item));
+        }
+    }
+
+    // a short query matches a good part of a whole system
+    if scored.len() > FILES_LIMIT {
+        scored.select_nth_unstable_by_key(FILES_LIMIT, |(score, depth, _)| (*score, *depth));
+        scored.truncate(FILES_LIMIT);
+    }
+    Some(scored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> app::AppEntry {
+        app::AppEntry {
+            id: name.to_lowercase(),
+            exec: name.to_lowercase(),
+            need_terminal: false,
+            icon: String::new(),
+            name: name.to_string(),
+            description: String::new(),
+            path: format!("/usr/share/applications/{}.desktop", name.to_lowercase()).into(),
+            mime_types: vec![],
+            aliases: vec![],
+            no_display: false,
+        }
+    }
+
+    /// Only the apps given, nothing read from the disk.
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.files.enabled = false;
+        config.locate.enabled = false;
+        config.recent.enabled = false;
+        config.executables.enabled = false;
+        config.browsers.enabled = false;
+        config.ssh.enabled = false;
+        config.windows.enabled = false;
+        config
+    }
+
+    #[tokio::test]
+    async fn outdated_queries_get_no_results() {
+        let (insender, rx) = mpsc::unbounded_channel();
+        let (outsender, outrx) = async_channel::unbounded();
+        let entries = vec![entry("Files"), entry("Firefox"), entry("Terminal")];
+        let manager = SearchManager::with_channels(&config(), entries, (rx, outsender));
+
+        // typed faster than searched
+        for query in ["f", "fi", "fir", "fire"] {
+            insender.send(SearchEvent::Term(query.to_string())).unwrap();
+        }
+        manager.manage();
+
+        let Ok(ManagerEvent::Results { query_id, sections }) = outrx.recv().await else {
+            panic!("expected results");
+        };
+        assert_eq!(query_id, 4);
+        let Some(Section::Apps(apps)) = sections.first() else {
+            panic!("expected apps first");
+        };
+        assert_eq!(apps[0].name, "Firefox");
+
+        insender.send(SearchEvent::RequestClose).unwrap();
+        drop(insender);
+        let mut rest = vec![];
+        while let Ok(event) = outrx.recv().await {
+            rest.push(event);
+        }
+        assert!(matches!(rest[..], [ManagerEvent::Close]));
+    }
+
+    #[tokio::test]
+    async fn answers_are_kept_once_another_query_starts() {
+        let (insender, rx) = mpsc::unbounded_channel();
+        let (outsender, outrx) = async_channel::unbounded();
+        SearchManager::with_channels(&config(), vec![], (rx, outsender)).manage();
+
+        let answer = |query: &str| {
+            insender.send(SearchEvent::Term(query.to_string())).unwrap();
+            let outrx = outrx.clone();
+            async move {
+                let Ok(ManagerEvent::Results { sections, .. }) = outrx.recv().await else {
+                    panic!("expected results");
+                };
+                sections.into_iter().find_map(|section| match section {
+                    Section::Mathematic(answer) => Some(answer.value),
+                    _ => None,
+                })
+            }
+        };
+
+        assert_eq!(answer("x = 3").await.as_deref(), Some("3"));
+        // still typing, `ans` is not the query's own answer
+        assert_eq!(answer("x = 3 * 2").await.as_deref(), Some("6"));
+        assert_eq!(answer("x * 2").await.as_deref(), Some("12"));
+        assert_eq!(answer("x * 2 + ans").await.as_deref(), Some("18"));
+        assert_eq!(answer("").await, None);
+        assert_eq!(answer("ans").await.as_deref(), Some("18"));
+    }
+
+    #[test]
+    fn cancelled_searches_return_none() {
+        let config = config();
+        let entries: Vec<app::AppEntry> = (0..200).map(|i| entry(&format!("App {i}"))).collect();
+        let conf = SearchConf::new(&config);
+        let run = |is_cancelled: &dyn Fn() -> bool| {
+            search(
+                &SkimMatcherV2::default(),
+                &entries,
+                &Sources::default(),
+                &conf,
+                "app",
+                is_cancelled,
+            )
+        };
+
+        assert!(run(&|| false).is_some());
+        assert!(run(&|| true).is_none());
+
+        // a newer query arriving halfway through the apps
+        let checks = std::cell::Cell::new(0);
+        let outdated = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 1
+        };
+        assert!(run(&outdated).is_none());
+    }
+
+    #[test]
+    fn unlisted_entries_are_not_searched() {
+        let config = config();
+        let helper = app::AppEntry {
+            no_display: true,
+            ..entry("Firefox Helper")
+        };
+        let entries = vec![entry("Firefox"), helper];
+        let conf = SearchConf::new(&config);
+        let sections = search(
+            &SkimMatcherV2::default(),
+            &entries,
+            &Sources::default(),
+            &conf,
+            "firefox",
+            || false,
+        )
+        .unwrap();
+        let Some(Section::Apps(apps)) = sections.first() else {
+            panic!("expected apps first");
+        };
+        let names: Vec<&str> = apps.iter().map(|app| app.name.as_str()).collect();
+        assert_eq!(names, ["Firefox"]);
+    }
+
+    #[test]
+    fn links_open_with_unlisted_scheme_handlers() {
+        let config = config();
+        let handler = app::AppEntry {
+            no_display: true,
+            ..entry("Compose")
+        };
+        let entries = vec![entry("Thunderbird"), handler];
+        let mut mime_apps = MimeApps::default();
+        mime_apps.add_list(
+            0,
+            Path::new("mimeapps.list"),
+            "[Default Applications]\nx-scheme-handler/mailto=compose.desktop\n",
+        );
+        let sources = Sources {
+            mime_apps: Arc::new(mime_apps),
+            ..Default::default()
+        };
+        let conf = SearchConf::new(&config);
+        let sections = search(
+            &SkimMatcherV2::default(),
+            &entries,
+            &sources,
+            &conf,
+            "mailto:me@example.com",
+            || false,
+        )
+        .unwrap();
+        let Some(Section::Url(link)) = sections.first() else {
+            panic!("expected a link first");
+        };
+        let handler = link.handler.as_ref().map(|handler| handler.id.as_str());
+        assert_eq!(handler, Some("compose"));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::app;
+use crate::executables::Executable;
+use crate::history::CommandHistory;
+use std::process::Command;
+
+const HISTORY_LIMIT: usize = 5;
+const EXECUTABLES_LIMIT: usize = 8;
+
+/// Where a suggested command comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    /// the query itself
+    Typed,
+    History,
+    /// an executable of `$PATH` completing the first word
+    Path,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellCommand {
+    pub command: String,
+    pub origin: Origin,
+}
+
+impl ShellCommand {
+    /// Runs the command with `sh`, in `term` or detached from seekr.
+    pub fn run(&self, in_terminal: bool, term: &str, term_launch_args: &[String]) {
+        if in_terminal {
+            app::launch_in_terminal(term, term_launch_args, &self.command);
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(&self.command);
+            app::spawn_detached(command);
+        }
+    }
+}
+
+/// The command of a `> htop` or `$ make` query.
+pub fn parse(query: &str) -> Option<(char, &str)> {
+    let query = query.trim_start();
+    let prefix = query.chars().next().filter(|c| matches!(c, '>' | '$'))?;
+    Some((prefix, query[1..].trim_start()))
+}
+
+/// The typed command, then the history entries it starts, then the
+/// executables completing its first word.
+pub fn suggest(
+    command: &str,
+    executables: &[Executable],
+    history: &CommandHistory,
+) -> Vec<ShellCommand> {
+    let command = command.trim_end();
+    let mut suggestions = vec![];
+    if !command.is_empty() {
+        suggestions.push(ShellCommand {
+            command: command.to_string(),
+            origin: Origin::Typed,
+        });
+    }
+
+    suggestions.extend(
+        history
+            .commands()
+            .filter(|known| known.starts_with(command) && *known != command)
+            .take(HISTORY_LIMIT)
+            .map(|known| ShellCommand {
+                command: known.clone(),
+                origin: Origin::History,
+            }),
+    );
+
+    // arguments are being typed once there is a space
+    if !command.is_empty() && !command.contains(char::is_whitespace) {
+        suggestions.extend(
+            executables
+                .iter()
+                .filter(|executable| {
+                    executable.name.starts_with(command) && executable.name != command
+                })
+                .take(EXECUTABLES_LIMIT)
+                .map(|executable| ShellCommand {
+                    command: executable.name.clone(),
+                    origin: Origin::Path,
+                }),
+        );
+    }
+    suggestions
+}
+
+/// What Tab turns the query into once `suggestion` is picked, executables
+/// get a space to type their arguments.
+pub fn completion(prefix: char, suggestion: &ShellCommand) -> String {
+    let space = if suggestion.origin == Origin::Path {
+        " "
+    } else {
+        ""
+    };
+    format!("{prefix} {}{space}", suggestion.command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn executables(names: &[&str]) -> Vec<Executable> {
+        names
+            .iter()
+            .map(|name| Executable {
+                name: name.to_string(),
+                path: PathBuf::from("/usr/bin").join(name),
+            })
+            .collect()
+    }
+
+    fn history(commands: &[&str]) -> CommandHistory {
+        commands.iter().map(|command| command.to_string()).collect()
+    }
+
+    fn commands(suggestions: &[ShellCommand]) -> Vec<(&str, Origin)> {
+        suggestions
+            .iter()
+            .map(|suggestion| (suggestion.command.as_str(), suggestion.origin))
+            .collect()
+    }
+
+    #[test]
+    fn parse_prefix() {
+        assert_eq!(parse("> htop"), Some(('>', "htop")));
+        assert_eq!(parse("  $make  -j4"), Some(('$', "make  -j4")));
+        assert_eq!(parse(">"), Some(('>', "")));
+        assert_eq!(parse("htop"), None);
+        assert_eq!(parse("=1+1"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn suggest_order() {
+        let executables = executables(&["git", "gitk", "git-lfs", "grep", "top"]);
+        let history = history(&["git status", "git", "gimp", "git log"]);
+        assert_eq!(
+            commands(&suggest("git", &executables, &history)),
+            [
+                ("git", Origin::Typed),
+                ("git status", Origin::History),
+                ("git log", Origin::History),
+                ("gitk", Origin::Path),
+                ("git-lfs", Origin::Path),
+            ]
+        );
+        // only history once arguments are typed
+        assert_eq!(
+            commands(&suggest("git l ", &executables, &history)),
+            [("git l", Origin::Typed), ("git log", Origin::History)]
+        );
+        // the whole history without a command
+        assert_eq!(suggest("", &executables, &history).len(), 4);
+    }
+
+    #[test]
+    fn suggest_limits() {
+        let names: Vec<_> = (0..20).map(|i| format!("x{i}")).collect();
+        let names: Vec<_> = names.iter().map(String::as_str).collect();
+        let suggestions = suggest("x", &executables(&names), &history(&names));
+        let count = |origin| suggestions.iter().filter(|s| s.origin == origin).count();
+        assert_eq!(count(Origin::Typed), 1);
+        assert_eq!(count(Origin::History), HISTORY_LIMIT);
+        assert_eq!(count(Origin::Path), EXECUTABLES_LIMIT);
+    }
+
+    #[test]
+    fn complete() {
+        let path = ShellCommand {
+            command: "gitk".to_string(),
+            origin: Origin::Path,
+        };
+        assert_eq!(completion('>', &path), "> gitk ");
+        let known = ShellCommand {
+            command: "git log".to_string(),
+            origin: Origin::History,
+        };
+        assert_eq!(completion('$', &known), "$ git log");
+    }
+}
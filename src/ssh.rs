@@ -0,0 +1,253 @@
+use crate::app;
+use crate::files::expand_home;
+use globset::Glob;
+use gtk::glib;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+// `Include` loops are cut there
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A host `ssh` can connect to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshHost {
+    /// the `Host` alias, or the name found in `known_hosts`
+    pub name: String,
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    /// non standard port of a `known_hosts` entry
+    pub port: Option<String>,
+}
+
+impl SshHost {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            hostname: None,
+            user: None,
+            port: None,
+        }
+    }
+
+    /// `user@hostname:port`, what the host stands for.
+    pub fn description(&self) -> String {
+        let mut description = self.hostname.clone().unwrap_or(self.name.clone());
+        if let Some(user) = &self.user {
+            description = format!("{user}@{description}");
+        }
+        if let Some(port) = &self.port {
+            description = format!("{description}:{port}");
+        }
+        description
+    }
+
+    /// Opens `ssh <host>` in the terminal.
+    pub fn connect(&self, term: String, term_launch_args: Vec<String>) {
+        app::launch_in_terminal(&term, &term_launch_args, &self.command());
+    }
+
+    /// The `ssh` command line, hosts never read as options.
+    fn command(&self) -> String {
+        let quote = |s: &str| glib::shell_quote(s).to_string_lossy().to_string();
+        match &self.port {
+            Some(port) => format!("ssh -p {} -- {}", quote(port), quote(&self.name)),
+            None => format!("ssh -- {}", quote(&self.name)),
+        }
+    }
+}
+
+fn ssh_dir() -> PathBuf {
+    expand_home("~/.ssh")
+}
+
+/// Hosts of `~/.ssh/config`, then the ones only found in `known_hosts`.
+pub fn load() -> Vec<SshHost> {
+    let dir = ssh_dir();
+    let mut hosts = vec![];
+    read_config(&dir, &dir.join("config"), 0, &mut hosts);
+    if let Ok(text) = std::fs::read_to_string(dir.join("known_hosts")) {
+        add_known_hosts(&text, &mut hosts);
+    }
+    debug!("found {} ssh hosts", hosts.len());
+    hosts
+}
+
+/// Patterns only match hosts, there is nothing to connect to.
+fn is_pattern(host: &str) -> bool {
+    host.contains(['*', '?', '!'])
+}
+
+/// `Keyword value`, `Keyword=value` or `Keyword = value`.
+fn split_line(line: &str) -> Option<(String, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let (keyword, rest) = line.split_at(end);
+    let value = rest.trim_start().strip_prefix('=').unwrap_or(rest).trim();
+    Some((keyword.to_lowercase(), value))
+}
+
+/// Files an `Include` argument stands for, relative paths start in
+/// `~/.ssh` and the last component may be a glob.
+fn include_paths(ssh_dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let path = match pattern.starts_with('~') {
+        true => expand_home(pattern),
+        false => ssh_dir.join(pattern),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if !is_pattern(&name) {
+        return vec![path];
+    }
+
+    let (Some(parent), Ok(glob)) = (path.parent(), Glob::new(&name)) else {
+        return vec![];
+    };
+    let matcher = glob.compile_matcher();
+    let Ok(read_dir) = std::fs::read_dir(parent) else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().is_some_and(|name| matcher.is_match(name)))
+        .collect();
+    // ssh reads them in lexical order
+    paths.sort();
+    paths
+}
+
+fn read_config(ssh_dir: &Path, path: &Path, depth: usize, hosts: &mut Vec<SshHost>) {
+    if depth > MAX_INCLUDE_DEPTH {
+        return;
+    }
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return;
+    };
+
+    // indices in `hosts` of the ones the following options apply to
+    let mut current: Vec<usize> = vec![];
+    for (keyword, value) in text.lines().filter_map(split_line) {
+        match keyword.as_str() {
+            "host" => {
+                current.clear();
+                for name in value.split_whitespace().filter(|name| !is_pattern(name)) {
+                    let i = match hosts.iter().position(|host| host.name == name) {
+                        Some(i) => i,
+                        None => {
+                            hosts.push(SshHost::new(name));
+                            hosts.len() - 1
+                        }
+                    };
+                    current.push(i);
+                }
+            }
+            "match" => current.clear(),
+            "include" => {
+                for pattern in value.split_whitespace() {
+                    for included in include_paths(ssh_dir, pattern) {
+                        read_config(ssh_dir, &included, depth + 1, hosts);
+                    }
+                }
+            }
+            // the first value given wins, like ssh does
+            "hostname" => {
+                for &i in &current {
+                    hosts[i].hostname.get_or_insert(value.to_string());
+                }
+            }
+            "user" => {
+                for &i in &current {
+                    hosts[i].user.get_or_insert(value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Adds the hosts of `known_hosts` not configured already, hashed entries
+/// can't be read back.
+fn add_known_hosts(text: &str, hosts: &mut Vec<SshHost>) {
+    let mut known: HashSet<String> = hosts
+        .iter()
+        .flat_map(|host| [Some(host.name.clone()), host.hostname.clone()])
+        .flatten()
+        .collect();
+
+    for line in text.lines() {
+        let line = line.trim();
+        // `@cert-authority` and `@revoked` lines are about keys
+        if line.is_empty() || line.starts_with('#') || line.starts_with('@') {
+            continue;
+        }
+        let Some(names) = line.split_whitespace().next() else {
+            continue;
+        };
+        if names.starts_with("|1|") {
+            continue;
+        }
+
+        for name in names.split(',').filter(|name| !is_pattern(name)) {
+            // `[host]:2222` is a host on a non standard port
+            let (host, port) = match name
+                .strip_prefix('[')
+                .and_then(|name| name.split_once("]:"))
+            {
+                Some((host, port)) => (host, Some(port.to_string())),
+                None => (name, None),
+            };
+            if known.insert(host.to_string()) {
+                hosts.push(SshHost {
+                    port,
+                    ..SshHost::new(host)
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_and_known_hosts() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ssh");
+        let mut hosts = vec![];
+        read_config(&dir, &dir.join("config"), 0, &mut hosts);
+        add_known_hosts(
+            &std::fs::read_to_string(dir.join("known_hosts")).unwrap(),
+            &mut hosts,
+        );
+
+        let described: Vec<(&str, String)> = hosts
+            .iter()
+            .map(|host| (host.name.as_str(), host.description()))
+            .collect();
+        assert_eq!(
+            described,
+            [
+                ("build", "build.internal".to_string()),
+                ("ci", "ci.internal".to_string()),
+                ("web", "deploy@web.example.com".to_string()),
+                ("db", "deploy@10.0.0.5".to_string()),
+                ("git.example.org", "git.example.org".to_string()),
+                ("192.168.1.20", "192.168.1.20".to_string()),
+                ("nas.local", "nas.local:2222".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn command() {
+        assert_eq!(SshHost::new("web").command(), "ssh -- 'web'");
+        let host = SshHost {
+            port: Some("2222".to_string()),
+            ..SshHost::new("-oProxyCommand=x")
+        };
+        assert_eq!(host.command(), "ssh -p '2222' -- '-oProxyCommand=x'");
+    }
+}
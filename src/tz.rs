@@ -0,0 +1,615 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+const LOCALTIME_FILE: &str = "/etc/localtime";
+
+pub const SECONDS_PER_DAY: i64 = 86400;
+
+lazy_static! {
+    /// Every zone of the system tzdata, keyed by lowercase id (`asia/tokyo`)
+    /// and by lowercase city (`tokyo`, `new york`).
+    static ref ZONE_INDEX: HashMap<String, PathBuf> = index_zones();
+}
+
+#[derive(Clone, Debug)]
+struct LocalType {
+    offset: i64,
+    abbr: String,
+}
+
+/// A time zone read from a TZif file.
+#[derive(Clone, Debug)]
+pub struct Zone {
+    transitions: Vec<(i64, usize)>,
+    types: Vec<LocalType>,
+    /// applies past the last transition, taken from the TZif footer
+    rule: Option<PosixRule>,
+}
+
+impl Default for Zone {
+    fn default() -> Self {
+        Self::utc()
+    }
+}
+
+impl Zone {
+    pub fn utc() -> Self {
+        Self {
+            transitions: vec![],
+            types: vec![LocalType {
+                offset: 0,
+                abbr: "UTC".to_string(),
+            }],
+            rule: None,
+        }
+    }
+
+    /// The zone of the system, from `$TZ` or `/etc/localtime`.
+    pub fn local() -> Self {
+        if let Ok(tz) = std::env::var("TZ") {
+            let name = tz.trim_start_matches(':');
+            if let Some(zone) = Self::find(name) {
+                return zone;
+            }
+            if let Some(zone) = Self::from_rule(name) {
+                return zone;
+            }
+        }
+
+        Self::load(Path::new(LOCALTIME_FILE)).unwrap_or_else(Self::utc)
+    }
+
+    /// A zone following a POSIX TZ rule such as `EST5EDT,M3.2.0,M11.1.0`
+    /// at all times.
+    pub fn from_rule(rule: &str) -> Option<Self> {
+        Some(Self {
+            rule: Some(PosixRule::parse(rule)?),
+            ..Self::utc()
+        })
+    }
+
+    /// Looks a zone up by id (`Asia/Tokyo`), city (`new york`) or
+    /// abbreviation with a file of its own (`UTC`, `CET`).
+    pub fn find(name: &str) -> Option<Self> {
+        let key = name.trim().to_lowercase().replace('_', " ");
+        if key == "utc" || key == "z" {
+            return Some(Self::utc());
+        }
+        Self::load(ZONE_INDEX.get(&key)?)
+    }
+
+    fn load(path: &Path) -> Option<Self> {
+        parse_tzif(&std::fs::read(path).ok()?)
+    }
+
+    /// The offset from UTC in seconds and the abbreviation in effect at the
+    /// instant `t`.
+    pub fn offset_at(&self, t: i64) -> (i64, &str) {
+        let last_transition = self.transitions.last().map(|(at, _)| *at);
+        if let Some(rule) = &self.rule {
+            if last_transition.map_or(true, |at| t >= at) {
+                return rule.offset_at(t);
+            }
+        }
+
+        let ty = match self.transitions.partition_point(|(at, _)| *at <= t) {
+            // before the first transition, the first standard time type
+            0 => self.types.first(),
+            i => self.types.get(self.transitions[i - 1].1),
+        };
+        ty.map(|ty| (ty.offset, ty.abbr.as_str()))
+            .unwrap_or((0, "UTC"))
+    }
+
+    /// Converts wall clock seconds to an instant, wall times repeated by a
+    /// DST change resolve to the offset before it, skipped ones to the
+    /// offset after it.
+    pub fn to_utc(&self, local: i64) -> i64 {
+        let guess = local - self.offset_at(local).0;
+        local - self.offset_at(guess).0
+    }
+}
+
+fn index_zones() -> HashMap<String, PathBuf> {
+    let mut index = HashMap::new();
+    let mut dirs = vec![PathBuf::from(ZONEINFO_DIR)];
+    while let Some(dir) = dirs.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in read_dir.filter_map(|e| e.ok()).map(|e| e.path()) {
+            let Ok(id) = path.strip_prefix(ZONEINFO_DIR) else {
+                continue;
+            };
+            let id = id.to_string_lossy().to_lowercase().replace('_', " ");
+            // duplicated trees with other leap second handling
+            if id.starts_with("posix") || id.starts_with("right") {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            // metadata such as zone1970.tab, not zones
+            if id.contains('.') || !id.chars().next().is_some_and(|c| c.is_alphabetic()) {
+                continue;
+            }
+
+            if let Some((_, city)) = id.rsplit_once('/') {
+                index.entry(city.to_string()).or_insert(path.clone());
+            }
+            index.insert(id, path);
+        }
+    }
+    index
+}
+
+fn read_be(data: &[u8], at: usize, len: usize) -> Option<i64> {
+    let bytes = data.get(at..at + len)?;
+    let unsigned = bytes.iter().fold(0u64, |acc, b| acc << 8 | *b as u64);
+    // sign extend
+    let shift = 64 - 8 * len as u32;
+    Some(((unsigned << shift) as i64) >> shift)
+}
+
+/// Parses a TZif file, see tzfile(5). Only the 64 bit data block of version
+/// 2+ files is read.
+fn parse_tzif(data: &[u8]) -> Option<Zone> {
+    let header = |at: usize| -> Option<[usize; 6]> {
+        if data.get(at..at + 4)? != b"TZif" {
+            return None;
+        }
+        let mut counts = [0; 6];
+        for (i, count) in counts.iter_mut().enumerate() {
+            *count = read_be(data, at + 20 + 4 * i, 4)? as usize;
+        }
+        Some(counts)
+    };
+
+    let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = header(0)?;
+    let version = *data.get(4)?;
+    let v1_len = timecnt * 5 + typecnt * 6 + charcnt + leapcnt * 8 + isstdcnt + isutcnt;
+
+    let (start, time_len) = if version >= b'2' {
+        (44 + v1_len, 8)
+    } else {
+        (0, 4)
+    };
+    let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = header(start)?;
+
+    let mut at = start + 44;
+    let mut transitions = Vec::with_capacity(timecnt);
+    for i in 0..timecnt {
+        let time = read_be(data, at + i * time_len, time_len)?;
+        let ty = *data.get(at + timecnt * time_len + i)? as usize;
+        transitions.push((time, ty));
+    }
+    at += timecnt * (time_len + 1);
+
+    let abbrs = data.get(at + typecnt * 6..at + typecnt * 6 + charcnt)?;
+    let mut types = Vec::with_capacity(typecnt);
+    for i in 0..typecnt {
+        let offset = read_be(data, at + i * 6, 4)?;
+        let abbr_start = *data.get(at + i * 6 + 5)? as usize;
+        let abbr = abbrs.get(abbr_start..)?.split(|b| *b == 0).next()?;
+        types.push(LocalType {
+            offset,
+            abbr: String::from_utf8_lossy(abbr).to_string(),
+        });
+    }
+    at += typecnt * 6 + charcnt + leapcnt * (time_len + 4) + isstdcnt + isutcnt;
+
+    let rule = (version >= b'2')
+        .then(|| data.get(at..))
+        .flatten()
+        .and_then(|footer| std::str::from_utf8(footer).ok())
+        .and_then(|footer| PosixRule::parse(footer.trim_matches('\n')));
+
+    if types.is_empty() {
+        return None;
+    }
+    Some(Zone {
+        transitions,
+        types,
+        rule,
+    })
+}
+
+/// When a DST period starts or ends in a year.
+#[derive(Clone, Copy, Debug)]
+enum DstDate {
+    /// `Mm.w.d`: day `d` (0 is Sunday) of week `w` of month `m`, week 5 is
+    /// the last one
+    MonthWeekDay(u32, u32, u32),
+    /// `Jn`: day of the year from 1 to 365, February 29th is never counted
+    Julian(u32),
+    /// `n`: day of the year from 0 to 365
+    Day(u32),
+}
+
+#[derive(Clone, Debug)]
+struct DstPeriod {
+    ty: LocalType,
+    /// the dates with the local time they happen at, in seconds past midnight
+    start: (DstDate, i64),
+    end: (DstDate, i64),
+}
+
+/// A POSIX `TZ` string such as `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Clone, Debug)]
+struct PosixRule {
+    std: LocalType,
+    dst: Option<DstPeriod>,
+}
+
+impl PosixRule {
+    fn parse(text: &str) -> Option<Self> {
+        let mut rest = text;
+        let std_abbr = take_abbr(&mut rest)?;
+        // POSIX offsets count westwards
+        let std_offset = -take_duration(&mut rest)?;
+        let std = LocalType {
+            offset: std_offset,
+            abbr: std_abbr,
+        };
+        if rest.is_empty() {
+            return Some(Self { std, dst: None });
+        }
+
+        let dst_abbr = take_abbr(&mut rest)?;
+        let dst_offset = if rest.starts_with(',') {
+            std_offset + 3600
+        } else {
+            -take_duration(&mut rest)?
+        };
+        let (start, end) = rest.strip_prefix(',')?.split_once(',')?;
+
+        Some(Self {
+            std,
+            dst: Some(DstPeriod {
+                ty: LocalType {
+                    offset: dst_offset,
+                    abbr: dst_abbr,
+                },
+                start: parse_dst_date(start)?,
+                end: parse_dst_date(end)?,
+            }),
+        })
+    }
+
+    fn offset_at(&self, t: i64) -> (i64, &str) {
+        let Some(DstPeriod {
+            ty: dst,
+            start,
+            end,
+        }) = &self.dst
+        else {
+            return (self.std.offset, &self.std.abbr);
+        };
+
+        let (year, _, _) = civil_from_days((t + self.std.offset).div_euclid(SECONDS_PER_DAY));
+        // the start is given in standard time, the end in daylight time
+        let start = dst_day(year, start.0) * SECONDS_PER_DAY + start.1 - self.std.offset;
+        let end = dst_day(year, end.0) * SECONDS_PER_DAY + end.1 - dst.offset;
+        let in_dst = if start < end {
+            start <= t && t < end
+        } else {
+            // southern hemisphere, DST spans the new year
+            !(end <= t && t < start)
+        };
+
+        if in_dst {
+            (dst.offset, &dst.abbr)
+        } else {
+            (self.std.offset, &self.std.abbr)
+        }
+    }
+}
+
+fn take_abbr(rest: &mut &str) -> Option<String> {
+    let (abbr, after) = match rest.strip_prefix('<') {
+        Some(quoted) => {
+            let (abbr, after) = quoted.split_once('>')?;
+            (abbr, after)
+        }
+        None => {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            rest.split_at(len)
+        }
+    };
+    if abbr.len() < 3 {
+        return None;
+    }
+    *rest = after;
+    Some(abbr.to_string())
+}
+
+/// `[+-]hh[:mm[:ss]]` in seconds.
+fn take_duration(rest: &mut &str) -> Option<i64> {
+    let len = rest
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, ':' | '+' | '-')))
+        .unwrap_or(rest.len());
+    let (text, after) = rest.split_at(len);
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(text) => (-1, text),
+        None => (1, text.trim_start_matches('+')),
+    };
+
+    let mut seconds = 0;
+    for (i, part) in text.split(':').enumerate() {
+        if i > 2 {
+            return None;
+        }
+        seconds += part.parse::<i64>().ok()? * [3600, 60, 1][i];
+    }
+
+    *rest = after;
+    Some(sign * seconds)
+}
+
+fn parse_dst_date(text: &str) -> Option<(DstDate, i64)> {
+    let (date, time) = match text.split_once('/') {
+        Some((date, mut time)) => (date, take_duration(&mut time)?),
+        None => (text, 2 * 3600),
+    };
+
+    let date = if let Some(mwd) = date.strip_prefix('M') {
+        let mut parts = mwd.split('.').map(|part| part.parse::<u32>().ok());
+        let (month, week, day) = (parts.next()??, parts.next()??, parts.next()??);
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || day > 6 {
+            return None;
+        }
+        DstDate::MonthWeekDay(month, week, day)
+    } else if let Some(julian) = date.strip_prefix('J') {
+        DstDate::Julian(julian.parse().ok().filter(|day| (1..=365).contains(day))?)
+    } else {
+        DstDate::Day(date.parse().ok().filter(|day| *day <= 365)?)
+    };
+
+    Some((date, time))
+}
+
+/// Days since the epoch of the given DST date in `year`.
+fn dst_day(year: i64, date: DstDate) -> i64 {
+    let jan_1 = days_from_civil(year, 1, 1);
+    match date {
+        DstDate::MonthWeekDay(month, week, day) => {
+            let first = days_from_civil(year, month, 1);
+            let first_match = first + (day as i64 - weekday(first) as i64).rem_euclid(7);
+            let mut res = first_match + 7 * (week as i64 - 1);
+            if week == 5 {
+                while civil_from_days(res).1 != month {
+                    res -= 7;
+                }
+            }
+            res
+        }
+        DstDate::Julian(n) => {
+            let leap_day = is_leap(year) && n >= 60;
+            jan_1 + n as i64 - 1 + leap_day as i64
+        }
+        DstDate::Day(n) => jan_1 + n as i64,
+    }
+}
+
+pub fn is_leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic gregorian date.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // shift the year to start in March so that leap days come last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The `(year, month, day)` of a day counted since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// 0 is Sunday.
+pub fn weekday(days: i64) -> u32 {
+    // 1970-01-01 was a Thursday
+    (days + 4).rem_euclid(7) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // DST changes in New York, the footer rule takes over after 2007
+    const DST_START_2006: i64 = 1144047600;
+    const DST_END_2006: i64 = 1162101600;
+    const DST_START_2007: i64 = 1173596400;
+    const DST_END_2007: i64 = 1194156000;
+    const DST_START_2024: i64 = 1710054000;
+    const DST_END_2024: i64 = 1730613600;
+
+    /// A version 2 TZif file with an empty version 1 block.
+    fn tzif(transitions: &[(i64, u8)], types: &[(i32, &str)], footer: &str) -> Vec<u8> {
+        let mut abbrs = vec![];
+        let mut indices = vec![];
+        for (_, abbr) in types {
+            indices.push(abbrs.len() as u8);
+            abbrs.extend_from_slice(abbr.as_bytes());
+            abbrs.push(0);
+        }
+
+        let header = |counts: [u32; 6]| {
+            let mut header = b"TZif2".to_vec();
+            header.extend_from_slice(&[0; 15]);
+            for count in counts {
+                header.extend_from_slice(&count.to_be_bytes());
+            }
+            header
+        };
+        let mut data = header([0; 6]);
+        data.extend(header([
+            0,
+            0,
+            0,
+            transitions.len() as u32,
+            types.len() as u32,
+            abbrs.len() as u32,
+        ]));
+        for (at, _) in transitions {
+            data.extend_from_slice(&at.to_be_bytes());
+        }
+        data.extend(transitions.iter().map(|(_, ty)| *ty));
+        for ((offset, abbr), index) in types.iter().zip(indices) {
+            data.extend_from_slice(&offset.to_be_bytes());
+            data.push(abbr.starts_with("ED") as u8);
+            data.push(index);
+        }
+        data.extend(abbrs);
+        data.extend(format!("\n{footer}\n").bytes());
+        data
+    }
+
+    fn new_york() -> Zone {
+        parse_tzif(&tzif(
+            &[
+                (DST_START_2006, 1),
+                (DST_END_2006, 0),
+                (DST_START_2007, 1),
+                (DST_END_2007, 0),
+            ],
+            &[(-18000, "EST"), (-14400, "EDT")],
+            "EST5EDT,M3.2.0,M11.1.0",
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn transitions() {
+        let zone = new_york();
+        assert_eq!(zone.offset_at(0), (-18000, "EST"));
+        assert_eq!(zone.offset_at(DST_START_2006 - 1), (-18000, "EST"));
+        assert_eq!(zone.offset_at(DST_START_2006), (-14400, "EDT"));
+        assert_eq!(zone.offset_at(DST_END_2006 - 1), (-14400, "EDT"));
+        assert_eq!(zone.offset_at(DST_END_2006), (-18000, "EST"));
+        // the 2006 rules ended on the last Sunday of October
+        assert_eq!(
+            zone.offset_at(DST_END_2006 + 3 * SECONDS_PER_DAY),
+            (-18000, "EST")
+        );
+        assert_eq!(zone.offset_at(DST_START_2007), (-14400, "EDT"));
+    }
+
+    #[test]
+    fn rule_past_the_last_transition() {
+        let zone = new_york();
+        assert_eq!(zone.offset_at(DST_START_2024 - 1), (-18000, "EST"));
+        assert_eq!(zone.offset_at(DST_START_2024), (-14400, "EDT"));
+        assert_eq!(zone.offset_at(DST_END_2024 - 1), (-14400, "EDT"));
+        assert_eq!(zone.offset_at(DST_END_2024), (-18000, "EST"));
+        // the rule keeps going, no data needed
+        assert_eq!(
+            zone.offset_at(4102444800 + 180 * SECONDS_PER_DAY),
+            (-14400, "EDT")
+        );
+    }
+
+    #[test]
+    fn wall_clock_to_utc() {
+        let zone = new_york();
+        let day = days_from_civil(2024, 6, 1) * SECONDS_PER_DAY;
+        assert_eq!(zone.to_utc(day + 12 * 3600), day + 16 * 3600);
+        let day = days_from_civil(2024, 12, 1) * SECONDS_PER_DAY;
+        assert_eq!(zone.to_utc(day + 12 * 3600), day + 17 * 3600);
+
+        // 01:30 happens twice on the first Sunday of November
+        let day = days_from_civil(2024, 11, 3) * SECONDS_PER_DAY;
+        assert_eq!(zone.to_utc(day + 5400), DST_END_2024 - 1800);
+        // 02:30 never happens on the second Sunday of March, it is 01:30 EST
+        let day = days_from_civil(2024, 3, 10) * SECONDS_PER_DAY;
+        assert_eq!(zone.to_utc(day + 9000), DST_START_2024 - 1800);
+    }
+
+    #[test]
+    fn posix_rules() {
+        let fixed = PosixRule::parse("<+0530>-5:30").unwrap();
+        assert_eq!(fixed.offset_at(0), (19800, "+0530"));
+
+        // DST spans the new year down south
+        let sydney = PosixRule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        let end = days_from_civil(2024, 4, 6) * SECONDS_PER_DAY + 16 * 3600;
+        let start = days_from_civil(2024, 10, 5) * SECONDS_PER_DAY + 16 * 3600;
+        assert_eq!(sydney.offset_at(end - 1), (39600, "AEDT"));
+        assert_eq!(sydney.offset_at(end), (36000, "AEST"));
+        assert_eq!(sydney.offset_at(start - 1), (36000, "AEST"));
+        assert_eq!(sydney.offset_at(start), (39600, "AEDT"));
+
+        // the last Sunday of March and October
+        let europe = PosixRule::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let start = days_from_civil(2024, 3, 31) * SECONDS_PER_DAY + 3600;
+        assert_eq!(europe.offset_at(start - 1), (3600, "CET"));
+        assert_eq!(europe.offset_at(start), (7200, "CEST"));
+
+        assert_eq!(
+            dst_day(2024, DstDate::Julian(60)),
+            days_from_civil(2024, 3, 1)
+        );
+        assert_eq!(
+            dst_day(2024, DstDate::Day(59)),
+            days_from_civil(2024, 2, 29)
+        );
+        assert!(PosixRule::parse("EST5EDT,M13.1.0,M11.1.0").is_none());
+        assert!(PosixRule::parse("X5").is_none());
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(2024, 2, 29), 19782);
+        assert_eq!(civil_from_days(-719468), (0, 3, 1));
+        assert_eq!(weekday(0), 4);
+
+        for days in (-1_000_000..1_000_000).step_by(97) {
+            let (year, month, day) = civil_from_days(days);
+            assert!(day >= 1 && day <= days_in_month(year, month));
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        for year in [1600, 1900, 1999, 2000, 2024, 2100] {
+            for month in 1..=12 {
+                let days = days_from_civil(year, month, days_in_month(year, month));
+                assert_eq!(civil_from_days(days + 1).2, 1);
+            }
+        }
+    }
+}
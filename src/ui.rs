@@ -1,79 +1,339 @@
 use crate::app::AppEntry;
-use crate::conf::Config;
+use crate::browser::Page;
+use crate::calc;
+use crate::executables::Executable;
+use crate::files::{self, FileEntry};
 use crate::icons;
-use crate::search::{self, SearchEvent};
-use gtk::glib;
+use crate::recent::RecentEntry;
+use crate::shell::{Origin, ShellCommand};
+use crate::ssh::SshHost;
+use crate::url::Link;
+use crate::websearch::WebSearch;
+use crate::windows::Window;
 use gtk::prelude::*;
-use std::sync::mpsc::Sender;
+use gtk::{gio, glib};
+use rust_i18n::t;
+
+/// A single line of the result list, stored boxed inside the list model.
+#[derive(Clone, PartialEq)]
+pub enum Row {
+    Title(String),
+    Mathematic(calc::Answer),
+    App(AppEntry),
+    File(FileEntry),
+    Executable(Executable),
+    Recent(RecentEntry),
+    Page(Page),
+    Url(Link),
+    WebSearch(WebSearch),
+    Shell(ShellCommand),
+    Ssh(SshHost),
+    Window(Window),
+    /// An app offered to open the uri.
+    OpenWith(AppEntry, String),
+}
 
 #[allow(non_snake_case)]
-pub fn EntryButton(config: &Config, entry: AppEntry, sender: &Sender<SearchEvent>) -> gtk::Button {
-    let tomanager = sender.clone();
+pub fn ResultFactory() -> gtk::SignalListItemFactory {
+    let factory = gtk::SignalListItemFactory::new();
 
-    let entry_button = gtk::Button::builder()
-        .vexpand(false)
+    factory.connect_setup(|_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Needs to be a ListItem");
+        item.set_child(Some(&RowWidget()));
+    });
+
+    factory.connect_bind(|_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Needs to be a ListItem");
+        let (Some(obj), Some(widget)) = (
+            item.item().and_downcast::<glib::BoxedAnyObject>(),
+            item.child(),
+        ) else {
+            return;
+        };
+
+        let row = obj.borrow::<Row>();
+        let activatable = !matches!(*row, Row::Title(_));
+        item.set_activatable(activatable);
+        item.set_selectable(activatable);
+        bind_row(&widget, &row);
+    });
+
+    factory
+}
+
+pub fn copy_to_clipboard(text: &str, primary: bool) {
+    let Some(display) = gtk::gdk::Display::default() else {
+        return;
+    };
+
+    display.clipboard().set_text(text);
+    if primary {
+        display.primary_clipboard().set_text(text);
+    }
+}
+
+/// Factory of the app drawer tiles, only binds `Row::App`.
+#[allow(non_snake_case)]
+pub fn DrawerFactory() -> gtk::SignalListItemFactory {
+    let factory = gtk::SignalListItemFactory::new();
+
+    factory.connect_setup(|_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Needs to be a ListItem");
+
+        let icon_image = gtk::Image::builder()
+            .pixel_size(48)
+            .css_name("entryIcon")
+            .build();
+        let name = gtk::Label::builder()
+            .css_name("entryName")
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .max_width_chars(12)
+            .halign(gtk::Align::Center)
+            .build();
+
+        let tile = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(5)
+            .css_name("entry")
+            .css_classes(["tile"])
+            .build();
+        tile.append(&icon_image);
+        tile.append(&name);
+
+        item.set_child(Some(&tile));
+    });
+
+    factory.connect_bind(|_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Needs to be a ListItem");
+        let (Some(obj), Some(tile)) = (
+            item.item().and_downcast::<glib::BoxedAnyObject>(),
+            item.child(),
+        ) else {
+            return;
+        };
+
+        let row = obj.borrow::<Row>();
+        if let Row::App(app) = &*row {
+            let icon_image = tile
+                .first_child()
+                .and_downcast::<gtk::Image>()
+                .expect("tile icon");
+            let name = icon_image
+                .next_sibling()
+                .and_downcast::<gtk::Label>()
+                .expect("tile name");
+
+            icon_image.set_from_gicon(&icons::get_icon(&app.icon));
+            name.set_label(&app.name);
+            tile.set_tooltip_text(Some(&app.description));
+        }
+    });
+
+    factory
+}
+
+/// Every row widget holds all the possible row layouts, binding a row only
+/// toggles which one is visible so that recycled widgets are never rebuilt.
+#[allow(non_snake_case)]
+fn RowWidget() -> gtk::Box {
+    let container = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
         .hexpand(true)
-        .can_focus(true)
-        .focus_on_click(true)
-        .sensitive(true)
-        .css_name("entry")
-        .name("Entry")
         .build();
 
-    let focus_controller = gtk::EventControllerFocus::new();
-    focus_controller.set_name(Some("gtk-box-focus-controller"));
+    let title = gtk::Label::builder()
+        .hexpand(true)
+        .halign(gtk::Align::Start)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .css_name("title")
+        .build();
+
+    let math_box = MathCard();
 
-    focus_controller.connect_enter(glib::clone!(
-        #[weak]
-        entry_button,
-        move |_| {
-            entry_button.add_css_class("focused");
-        }
-    ));
+    container.append(&title);
+    container.append(&math_box);
+    container.append(&EntryRow());
+
+    container
+}
 
-    focus_controller.connect_leave(glib::clone!(
-        #[weak]
-        entry_button,
-        move |_| {
-            entry_button.remove_css_class("focused");
+fn bind_row(widget: &gtk::Widget, row: &Row) {
+    let title = widget
+        .first_child()
+        .and_downcast::<gtk::Label>()
+        .expect("row title");
+    let math_box = title.next_sibling().expect("row math card");
+    let entry = math_box.next_sibling().expect("row entry");
+
+    title.set_visible(matches!(row, Row::Title(_)));
+    math_box.set_visible(matches!(row, Row::Mathematic(_)));
+    entry.set_visible(!matches!(row, Row::Title(_) | Row::Mathematic(_)));
+
+    match row {
+        Row::Title(text) => title.set_label(text),
+        Row::Mathematic(res) => {
+            let expr = math_box
+                .last_child()
+                .and_then(|answer_box| answer_box.first_child())
+                .and_downcast::<gtk::Label>()
+                .expect("math expression");
+            let answer = expr
+                .next_sibling()
+                .and_downcast::<gtk::Label>()
+                .expect("math answer");
+            let bases = answer
+                .next_sibling()
+                .and_downcast::<gtk::Grid>()
+                .expect("math bases");
+            expr.set_text(&res.expr);
+            answer.set_text(&res.value);
+
+            bases.set_visible(!res.bases.is_empty());
+            for (i, (name, value)) in res.bases.iter().enumerate() {
+                let labels = (bases.child_at(0, i as i32), bases.child_at(1, i as i32));
+                if let (Some(name_label), Some(value_label)) = labels {
+                    name_label
+                        .downcast::<gtk::Label>()
+                        .expect("base name")
+                        .set_text(name);
+                    value_label
+                        .downcast::<gtk::Label>()
+                        .expect("base value")
+                        .set_text(value);
+                }
+            }
+        }
+        Row::App(app) | Row::OpenWith(app, _) => bind_entry(
+            &entry,
+            &icons::get_icon(&app.icon),
+            &app.name,
+            &app.description,
+        ),
+        Row::File(file) => bind_entry(
+            &entry,
+            &icons::file_icon(&file.path, file.is_dir),
+            &file.name,
+            &file.location(),
+        ),
+        Row::Executable(executable) => bind_entry(
+            &entry,
+            &icons::get_icon("application-x-executable"),
+            &executable.name,
+            &files::abbreviate_home(&executable.path),
+        ),
+        Row::Url(link) => {
+            let (icon, description) = match &link.handler {
+                Some(app) => (
+                    icons::get_icon(&app.icon),
+                    t!("open_url_with", app = app.name).to_string(),
+                ),
+                None => (icons::get_icon("web-browser"), t!("open_url").to_string()),
+            };
+            bind_entry(&entry, &icon, &link.url, &description)
+        }
+        Row::WebSearch(search) => bind_entry(
+            &entry,
+            &icons::get_icon("web-browser"),
+            &t!("search_web", engine = search.engine, terms = search.terms),
+            &search.url,
+        ),
+        Row::Shell(shell) => {
+            let (icon, description) = match shell.origin {
+                Origin::Typed => ("utilities-terminal", t!("run_command")),
+                Origin::History => ("document-open-recent", t!("command_history")),
+                Origin::Path => ("application-x-executable", t!("executable")),
+            };
+            bind_entry(&entry, &icons::get_icon(icon), &shell.command, &description)
         }
-    ));
-
-    let term = config.general.terminal.clone();
-    let args = config.general.args.clone();
-    entry_button.connect_clicked(glib::clone!(
-        #[strong]
-        entry,
-        #[strong]
-        tomanager,
-        move |_| {
-            entry.launch(term.clone(), args.clone());
-            let _ = tomanager.send(search::SearchEvent::RequestClose);
+        Row::Window(window) => {
+            let icon = window.icon.as_deref().unwrap_or(&window.app_id);
+            let description = match &window.workspace {
+                Some(workspace) => t!(
+                    "switch_to_window_on",
+                    app = window.app_id,
+                    workspace = workspace
+                ),
+                None => t!("switch_to_window", app = window.app_id),
+            };
+            bind_entry(&entry, &icons::get_icon(icon), &window.title, &description)
         }
-    ));
+        Row::Ssh(host) => bind_entry(
+            &entry,
+            &icons::get_icon("network-server"),
+            &host.name,
+            &t!("ssh_to", host = host.description()),
+        ),
+        Row::Page(page) => {
+            let icon = match &page.handler {
+                Some(app) => icons::get_icon(&app.icon),
+                None => icons::get_icon("web-browser"),
+            };
+            let title = if page.title.is_empty() {
+                &page.url
+            } else {
+                &page.title
+            };
+            let description = match page.bookmarked {
+                true => t!("bookmark_in", browser = page.browser.name(), url = page.url),
+                false => t!("history_in", browser = page.browser.name(), url = page.url),
+            };
+            bind_entry(&entry, &icon, title, &description)
+        }
+        Row::Recent(recent) => bind_entry(
+            &entry,
+            &icons::mime_icon(&recent.mime_type),
+            &recent.name,
+            &recent.description(),
+        ),
+    }
+}
 
-    entry_button.add_controller(focus_controller);
-    entry_button.set_focusable(true);
+fn bind_entry(entry: &gtk::Widget, icon: &gio::Icon, name: &str, description: &str) {
+    let icon_image = entry
+        .first_child()
+        .and_downcast::<gtk::Image>()
+        .expect("entry icon");
+    let labels = icon_image.next_sibling().expect("entry labels");
+    let name_label = labels
+        .first_child()
+        .and_downcast::<gtk::Label>()
+        .expect("entry name");
+    let desc_label = name_label
+        .next_sibling()
+        .and_downcast::<gtk::Label>()
+        .expect("entry description");
 
+    icon_image.set_from_gicon(icon);
+    name_label.set_label(name);
+    desc_label.set_label(description);
+}
+
+#[allow(non_snake_case)]
+fn EntryRow() -> gtk::Box {
     let icon_image = gtk::Image::builder()
         .pixel_size(48)
         .css_name("entryIcon")
         .build();
-    icon_image.set_from_gicon(&icons::get_icon(&entry.icon));
 
     let name = gtk::Label::builder()
         .css_name("entryName")
         .ellipsize(gtk::pango::EllipsizeMode::End)
         .halign(gtk::Align::Start)
         .build();
-    name.set_label(&entry.name);
 
     let desc = gtk::Label::builder()
         .css_name("entryDescription")
         .halign(gtk::Align::Start)
         .ellipsize(gtk::pango::EllipsizeMode::End)
         .build();
-    desc.set_label(&entry.description);
 
     let labels = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
@@ -86,17 +346,133 @@ pub fn EntryButton(config: &Config, entry: AppEntry, sender: &Sender<SearchEvent
     labels.append(&name);
     labels.append(&desc);
 
-    let content_box = gtk::Box::builder()
+    let entry_box = gtk::Box::builder()
         .orientation(gtk::Orientation::Horizontal)
         .spacing(10)
+        .vexpand(false)
         .hexpand(true)
         .valign(gtk::Align::Center)
+        .css_name("entry")
+        .name("Entry")
+        .build();
+    entry_box.append(&icon_image);
+    entry_box.append(&labels);
+
+    entry_box
+}
+
+/// Replaces the content of `store` with `rows` in a single `items-changed`
+/// emission, rows shared with the previous content at both ends are kept.
+pub fn replace_rows(store: &gio::ListStore, rows: Vec<Row>) {
+    let n_old = store.n_items() as usize;
+    let same = |i: usize, row: &Row| {
+        store
+            .item(i as u32)
+            .and_downcast::<glib::BoxedAnyObject>()
+            .is_some_and(|obj| *obj.borrow::<Row>() == *row)
+    };
+
+    let prefix = rows
+        .iter()
+        .enumerate()
+        .take_while(|(i, row)| *i < n_old && same(*i, row))
+        .count();
+    let suffix = (0..(n_old - prefix).min(rows.len() - prefix))
+        .take_while(|k| same(n_old - 1 - k, &rows[rows.len() - 1 - k]))
+        .count();
+
+    let n_removals = n_old - prefix - suffix;
+    let n_additions = rows.len() - prefix - suffix;
+    if n_removals == 0 && n_additions == 0 {
+        return;
+    }
+
+    let additions: Vec<glib::BoxedAnyObject> = rows
+        .into_iter()
+        .skip(prefix)
+        .take(n_additions)
+        .map(glib::BoxedAnyObject::new)
+        .collect();
+    store.splice(prefix as u32, n_removals as u32, &additions);
+}
+
+#[allow(non_snake_case)]
+fn MathCard() -> gtk::Box {
+    let math_box = gtk::Box::builder()
+        .css_name("mathResult")
+        .hexpand(true)
+        .height_request(395)
+        .orientation(gtk::Orientation::Vertical)
+        .build();
+    let head_box = gtk::Box::builder()
+        .css_classes(["head"])
+        .hexpand(true)
+        .spacing(5)
+        .halign(gtk::Align::Center)
+        .build();
+    let title = gtk::Label::builder()
+        .hexpand(true)
         .halign(gtk::Align::Start)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
         .build();
-    content_box.append(&icon_image);
-    content_box.append(&labels);
 
-    entry_button.set_child(Some(&content_box));
+    title.set_text(&t!("expr_eval"));
+    let head_icon = gtk::Image::builder()
+        .pixel_size(12)
+        .gicon(&icons::get_icon("plus-symbolic"))
+        .build();
+    head_icon.set_css_classes(&["eval_icon"]);
+
+    head_box.append(&head_icon);
+    head_box.append(&title);
+
+    let answer_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .hexpand(true)
+        .vexpand(true)
+        .css_classes(["answer_box"])
+        .halign(gtk::Align::Center)
+        .valign(gtk::Align::Center)
+        .build();
+
+    let expr = gtk::Label::builder()
+        .css_classes(["expr"])
+        .halign(gtk::Align::Center)
+        .ellipsize(gtk::pango::EllipsizeMode::Middle)
+        .build();
+    let answer = gtk::Label::builder()
+        .css_classes(["answer"])
+        .halign(gtk::Align::Center)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .build();
+    // integer results in every base, one line each
+    let bases = gtk::Grid::builder()
+        .css_classes(["bases"])
+        .column_spacing(10)
+        .halign(gtk::Align::Center)
+        .build();
+    for row in 0..4 {
+        let name = gtk::Label::builder()
+            .css_classes(["base_name"])
+            .halign(gtk::Align::End)
+            .build();
+        let value = gtk::Label::builder()
+            .css_classes(["base_value"])
+            .halign(gtk::Align::Start)
+            .ellipsize(gtk::pango::EllipsizeMode::Middle)
+            .max_width_chars(48)
+            .build();
+        bases.attach(&name, 0, row, 1, 1);
+        bases.attach(&value, 1, row, 1, 1);
+    }
+
+    answer_box.append(&expr);
+    answer_box.append(&answer);
+    answer_box.append(&bases);
+
+    math_box.append(&head_box);
+    math_box.append(&answer_box);
 
-    entry_button
+    math_box
 }
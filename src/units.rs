@@ -0,0 +1,713 @@
+use crate::calc::{format_number, Answer};
+use crate::conf::CalcConf;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Dimension {
+    Length,
+    Mass,
+    Temperature,
+    Volume,
+    Data,
+    Time,
+    Speed,
+    Area,
+    Energy,
+}
+
+/// Which prefixes a unit accepts.
+#[derive(Clone, Copy, PartialEq)]
+enum Prefixes {
+    None,
+    Si,
+    /// SI multiples and binary ones, `kB` and `KiB`
+    Data,
+}
+
+struct Unit {
+    dimension: Dimension,
+    /// case sensitive abbreviations, `km` is the `k` prefix applied to `m`
+    symbols: &'static [&'static str],
+    /// lowercase names, plurals included
+    names: &'static [&'static str],
+    /// how many base units of the dimension one unit is worth
+    factor: f64,
+    /// added before scaling, only temperatures have one
+    offset: f64,
+    prefixes: Prefixes,
+}
+
+const fn unit(
+    dimension: Dimension,
+    symbols: &'static [&'static str],
+    names: &'static [&'static str],
+    factor: f64,
+    prefixes: Prefixes,
+) -> Unit {
+    Unit {
+        dimension,
+        symbols,
+        names,
+        factor,
+        offset: 0.0,
+        prefixes,
+    }
+}
+
+// (symbol, name, factor)
+const SI_PREFIXES: &[(&str, &str, f64)] = &[
+    ("n", "nano", 1e-9),
+    ("u", "micro", 1e-6),
+    ("µ", "micro", 1e-6),
+    ("m", "milli", 1e-3),
+    ("c", "centi", 1e-2),
+    ("d", "deci", 1e-1),
+    ("k", "kilo", 1e3),
+    ("M", "mega", 1e6),
+    ("G", "giga", 1e9),
+    ("T", "tera", 1e12),
+    ("P", "peta", 1e15),
+];
+
+const BINARY_PREFIXES: &[(&str, &str, f64)] = &[
+    ("Ki", "kibi", 1024.0),
+    ("Mi", "mebi", 1048576.0),
+    ("Gi", "gibi", 1073741824.0),
+    ("Ti", "tebi", 1099511627776.0),
+    ("Pi", "pebi", 1125899906842624.0),
+];
+
+// base units: meter, kilogram, kelvin, liter, byte, second, meter per
+// second, square meter and joule
+const UNITS: &[Unit] = &[
+    // length
+    unit(
+        Dimension::Length,
+        &["m"],
+        &["meter", "meters", "metre", "metres"],
+        1.0,
+        Prefixes::Si,
+    ),
+    unit(
+        Dimension::Length,
+        &["in"],
+        &["inch", "inches"],
+        0.0254,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Length,
+        &["ft"],
+        &["foot", "feet"],
+        0.3048,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Length,
+        &["yd"],
+        &["yard", "yards"],
+        0.9144,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Length,
+        &["mi"],
+        &["mile", "miles"],
+        1609.344,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Length,
+        &["nmi"],
+        &["nautical mile", "nautical miles"],
+        1852.0,
+        Prefixes::None,
+    ),
+    // mass
+    unit(
+        Dimension::Mass,
+        &["g"],
+        &["gram", "grams", "gramme", "grammes"],
+        1e-3,
+        Prefixes::Si,
+    ),
+    unit(
+        Dimension::Mass,
+        &["t"],
+        &["tonne", "tonnes"],
+        1000.0,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Mass,
+        &["lb", "lbs"],
+        &["pound", "pounds"],
+        0.45359237,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Mass,
+        &["oz"],
+        &["ounce", "ounces"],
+        0.028349523125,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Mass,
+        &["st"],
+        &["stone", "stones"],
+        6.35029318,
+        Prefixes::None,
+    ),
+    // temperature
+    unit(
+        Dimension::Temperature,
+        &["K"],
+        &["kelvin", "kelvins"],
+        1.0,
+        Prefixes::None,
+    ),
+    Unit {
+        offset: 273.15,
+        ..unit(
+            Dimension::Temperature,
+            &["C", "°C"],
+            &["celsius"],
+            1.0,
+            Prefixes::None,
+        )
+    },
+    Unit {
+        offset: 459.67,
+        ..unit(
+            Dimension::Temperature,
+            &["F", "°F"],
+            &["fahrenheit"],
+            5.0 / 9.0,
+            Prefixes::None,
+        )
+    },
+    // volume
+    unit(
+        Dimension::Volume,
+        &["l", "L"],
+        &["liter", "liters", "litre", "litres"],
+        1.0,
+        Prefixes::Si,
+    ),
+    unit(
+        Dimension::Volume,
+        &["m3", "m³"],
+        &["cubic meter", "cubic meters"],
+        1000.0,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Volume,
+        &["cup"],
+        &["cup", "cups"],
+        0.2365882365,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Volume,
+        &["tsp"],
+        &["teaspoon", "teaspoons"],
+        0.00492892159375,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Volume,
+        &["tbsp"],
+        &["tablespoon", "tablespoons"],
+        0.01478676478125,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Volume,
+        &["floz", "fl oz"],
+        &["fluid ounce", "fluid ounces"],
+        0.0295735295625,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Volume,
+        &["pt"],
+        &["pint", "pints"],
+        0.473176473,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Volume,
+        &["qt"],
+        &["quart", "quarts"],
+        0.946352946,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Volume,
+        &["gal"],
+        &["gallon", "gallons"],
+        3.785411784,
+        Prefixes::None,
+    ),
+    // data
+    unit(
+        Dimension::Data,
+        &["B"],
+        &["byte", "bytes"],
+        1.0,
+        Prefixes::Data,
+    ),
+    unit(
+        Dimension::Data,
+        &["b", "bit"],
+        &["bit", "bits"],
+        0.125,
+        Prefixes::Data,
+    ),
+    // time
+    unit(
+        Dimension::Time,
+        &["s", "sec"],
+        &["second", "seconds"],
+        1.0,
+        Prefixes::Si,
+    ),
+    unit(
+        Dimension::Time,
+        &["min"],
+        &["minute", "minutes"],
+        60.0,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Time,
+        &["h", "hr"],
+        &["hour", "hours"],
+        3600.0,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Time,
+        &["d"],
+        &["day", "days"],
+        86400.0,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Time,
+        &["wk"],
+        &["week", "weeks"],
+        604800.0,
+        Prefixes::None,
+    ),
+    // a twelfth of a julian year
+    unit(
+        Dimension::Time,
+        &["mo"],
+        &["month", "months"],
+        2629800.0,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Time,
+        &["y", "yr"],
+        &["year", "years"],
+        31557600.0,
+        Prefixes::None,
+    ),
+    // speed
+    unit(
+        Dimension::Speed,
+        &["m/s"],
+        &["meter per second", "meters per second"],
+        1.0,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Speed,
+        &["km/h", "kmh", "kph"],
+        &["kilometer per hour", "kilometers per hour"],
+        1.0 / 3.6,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Speed,
+        &["mph"],
+        &["mile per hour", "miles per hour"],
+        0.44704,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Speed,
+        &["ft/s", "fps"],
+        &[],
+        0.3048,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Speed,
+        &["kn", "kt"],
+        &["knot", "knots"],
+        1852.0 / 3600.0,
+        Prefixes::None,
+    ),
+    // area
+    unit(
+        Dimension::Area,
+        &["m2", "m²"],
+        &["square meter", "square meters"],
+        1.0,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["km2", "km²"],
+        &["square kilometer", "square kilometers"],
+        1e6,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["cm2", "cm²"],
+        &["square centimeter", "square centimeters"],
+        1e-4,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["mm2", "mm²"],
+        &["square millimeter", "square millimeters"],
+        1e-6,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["ha"],
+        &["hectare", "hectares"],
+        1e4,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["ac"],
+        &["acre", "acres"],
+        4046.8564224,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["ft2", "ft²", "sqft"],
+        &["square foot", "square feet"],
+        0.09290304,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["in2", "in²", "sqin"],
+        &["square inch", "square inches"],
+        0.00064516,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["yd2", "yd²"],
+        &["square yard", "square yards"],
+        0.83612736,
+        Prefixes::None,
+    ),
+    unit(
+        Dimension::Area,
+        &["mi2", "mi²", "sqmi"],
+        &["square mile", "square miles"],
+        2589988.110336,
+        Prefixes::None,
+    ),
+    // energy
+    unit(
+        Dimension::Energy,
+        &["J"],
+        &["joule", "joules"],
+        1.0,
+        Prefixes::Si,
+    ),
+    unit(
+        Dimension::Energy,
+        &["cal"],
+        &["calorie", "calories"],
+        4.184,
+        Prefixes::Si,
+    ),
+    unit(
+        Dimension::Energy,
+        &["Wh"],
+        &["watt hour", "watt hours"],
+        3600.0,
+        Prefixes::Si,
+    ),
+    unit(
+        Dimension::Energy,
+        &["eV"],
+        &["electronvolt", "electronvolts"],
+        1.602176634e-19,
+        Prefixes::Si,
+    ),
+    unit(
+        Dimension::Energy,
+        &["BTU", "Btu"],
+        &["btu", "btus"],
+        1055.05585262,
+        Prefixes::None,
+    ),
+];
+
+// `5 km in mi`, `100 F to C`, `3 cups as ml`
+const CONNECTIVES: [&str; 3] = [" in ", " to ", " as "];
+
+impl Unit {
+    fn prefixes(&self) -> impl Iterator<Item = &'static (&'static str, &'static str, f64)> {
+        let (si, binary): (&[_], &[_]) = match self.prefixes {
+            Prefixes::None => (&[], &[]),
+            Prefixes::Si => (SI_PREFIXES, &[]),
+            Prefixes::Data => (SI_PREFIXES, BINARY_PREFIXES),
+        };
+        let data = self.prefixes == Prefixes::Data;
+        // there is no such thing as a millibyte
+        si.iter()
+            .filter(move |(_, _, factor)| !data || *factor > 1.0)
+            .chain(binary)
+    }
+
+    fn to_base(&self, value: f64, scale: f64) -> f64 {
+        (value * scale + self.offset) * self.factor
+    }
+
+    fn in_unit(&self, value: f64, scale: f64) -> f64 {
+        (value / self.factor - self.offset) / scale
+    }
+}
+
+/// Finds the unit `text` designates, with the factor of its prefix.
+fn find_unit(text: &str) -> Option<(&'static Unit, f64)> {
+    let lower = text.to_lowercase();
+    let exact = UNITS
+        .iter()
+        .find(|unit| unit.symbols.contains(&text) || unit.names.contains(&lower.as_str()));
+    if let Some(unit) = exact {
+        return Some((unit, 1.0));
+    }
+
+    UNITS.iter().find_map(|unit| {
+        unit.prefixes().find_map(|(symbol, name, factor)| {
+            let by_symbol = text
+                .strip_prefix(symbol)
+                .is_some_and(|rest| unit.symbols.contains(&rest));
+            let by_name = lower
+                .strip_prefix(name)
+                .is_some_and(|rest| unit.names.contains(&rest));
+            (by_symbol || by_name).then_some((unit, *factor))
+        })
+    })
+}
+
+/// `5km` and `5 km` both give `(5.0, "km")`.
+fn split_quantity(text: &str) -> Option<(f64, &str)> {
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+')))
+        .unwrap_or(text.len());
+    let value = text[..end].parse().ok()?;
+    let unit = text[end..].trim();
+    (!unit.is_empty()).then_some((value, unit))
+}
+
+/// Converts `value` from one unit to the other, `None` if either is unknown
+/// or they measure different things.
+fn convert_value(value: f64, from: &str, to: &str) -> Option<f64> {
+    let (from, from_scale) = find_unit(from)?;
+    let (to, to_scale) = find_unit(to)?;
+    if from.dimension != to.dimension {
+        return None;
+    }
+    Some(to.in_unit(from.to_base(value, from_scale), to_scale))
+}
+
+/// Answers `<quantity> <unit> in|to|as <unit>` queries.
+pub fn convert(query: &str, conf: &CalcConf) -> Option<Answer> {
+    let query = query.trim();
+    // the last connective wins, `5 in in cm` converts inches
+    let (at, connective) = CONNECTIVES
+        .iter()
+        .filter_map(|connective| Some((query.rfind(connective)?, connective)))
+        .max_by_key(|(at, _)| *at)?;
+    let (quantity, target) = (&query[..at], query[at + connective.len()..].trim());
+
+    let (value, source) = split_quantity(quantity.trim())?;
+    let result = convert_value(value, source, target)?;
+    let plain = format_number(
+        result,
+        &CalcConf {
+            thousands_separator: String::new(),
+            ..conf.clone()
+        },
+    );
+
+    Some(Answer {
+        expr: format!(
+            "{} {source}{connective}{target}",
+            format_number(value, conf)
+        ),
+        value: format!("{} {target}", format_number(result, conf)),
+        plain,
+        result,
+        assignments: vec![],
+        bases: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_converts(value: f64, from: &str, to: &str, expected: f64) {
+        let result = convert_value(value, from, to)
+            .unwrap_or_else(|| panic!("{value} {from} in {to} did not convert"));
+        let tolerance = expected.abs().max(1.0) * 1e-9;
+        assert!(
+            (result - expected).abs() <= tolerance,
+            "{value} {from} in {to}: expected {expected}, got {result}"
+        );
+    }
+
+    #[test]
+    fn length() {
+        assert_converts(5.0, "km", "miles", 3.106855961);
+        assert_converts(1.0, "mi", "m", 1609.344);
+        assert_converts(12.0, "in", "ft", 1.0);
+        assert_converts(3.0, "ft", "yd", 1.0);
+        assert_converts(1.0, "nmi", "m", 1852.0);
+        assert_converts(2.0, "kilometers", "meters", 2000.0);
+        assert_converts(25.4, "mm", "inch", 1.0);
+        assert_converts(1.0, "metre", "cm", 100.0);
+    }
+
+    #[test]
+    fn mass() {
+        assert_converts(1.0, "kg", "g", 1000.0);
+        assert_converts(1.0, "lb", "oz", 16.0);
+        assert_converts(1.0, "st", "lbs", 14.0);
+        assert_converts(1.0, "t", "kg", 1000.0);
+        assert_converts(500.0, "mg", "g", 0.5);
+        assert_converts(1.0, "pound", "kilograms", 0.45359237);
+    }
+
+    #[test]
+    fn temperature() {
+        assert_converts(100.0, "F", "C", 37.777777778);
+        assert_converts(0.0, "C", "F", 32.0);
+        assert_converts(-40.0, "°C", "°F", -40.0);
+        assert_converts(0.0, "K", "celsius", -273.15);
+        assert_converts(32.0, "fahrenheit", "kelvin", 273.15);
+    }
+
+    #[test]
+    fn volume() {
+        assert_converts(3.0, "cups", "ml", 709.7647095);
+        assert_converts(1.0, "gal", "qt", 4.0);
+        assert_converts(1.0, "qt", "pt", 2.0);
+        assert_converts(1.0, "tbsp", "tsp", 3.0);
+        assert_converts(1.0, "cup", "fl oz", 8.0);
+        assert_converts(1.0, "m3", "L", 1000.0);
+        assert_converts(2.0, "litres", "dl", 20.0);
+    }
+
+    #[test]
+    fn data() {
+        assert_converts(2.0, "GiB", "MB", 2147.483648);
+        assert_converts(1.0, "MB", "kB", 1000.0);
+        assert_converts(1.0, "KiB", "B", 1024.0);
+        assert_converts(1.0, "B", "b", 8.0);
+        assert_converts(1.0, "Gb", "MB", 125.0);
+        assert_converts(1.0, "TiB", "GiB", 1024.0);
+        assert_converts(1.0, "gibibytes", "megabytes", 1073.741824);
+        // no fraction of a byte or a bit
+        assert_eq!(convert_value(1.0, "mB", "B"), None);
+    }
+
+    #[test]
+    fn time() {
+        assert_converts(1.0, "h", "min", 60.0);
+        assert_converts(1.0, "d", "s", 86400.0);
+        assert_converts(1.0, "wk", "days", 7.0);
+        assert_converts(1.0, "yr", "mo", 12.0);
+        assert_converts(1.0, "s", "ms", 1000.0);
+        assert_converts(1.0, "ms", "µs", 1000.0);
+        assert_converts(90.0, "minutes", "hours", 1.5);
+    }
+
+    #[test]
+    fn speed() {
+        assert_converts(100.0, "km/h", "m/s", 27.777777778);
+        assert_converts(60.0, "mph", "km/h", 96.56064);
+        assert_converts(1.0, "kn", "km/h", 1.852);
+        assert_converts(1.0, "ft/s", "m/s", 0.3048);
+        assert_converts(10.0, "knots", "kph", 18.52);
+    }
+
+    #[test]
+    fn area() {
+        assert_converts(1.0, "km2", "m²", 1e6);
+        assert_converts(1.0, "ha", "m2", 1e4);
+        assert_converts(1.0, "ac", "sqft", 43560.0);
+        assert_converts(1.0, "mi2", "acres", 640.0);
+        assert_converts(1.0, "ft2", "in2", 144.0);
+        assert_converts(1.0, "yd²", "sqft", 9.0);
+        assert_converts(1.0, "cm2", "mm2", 100.0);
+    }
+
+    #[test]
+    fn energy() {
+        assert_converts(1.0, "kcal", "kJ", 4.184);
+        assert_converts(1.0, "kWh", "MJ", 3.6);
+        assert_converts(1.0, "BTU", "J", 1055.05585262);
+        assert_converts(1.0, "eV", "J", 1.602176634e-19);
+        assert_converts(1.0, "Wh", "joules", 3600.0);
+    }
+
+    #[test]
+    fn incompatible_or_unknown_units() {
+        assert_eq!(convert_value(1.0, "km", "kg"), None);
+        assert_eq!(convert_value(1.0, "C", "J"), None);
+        assert_eq!(convert_value(1.0, "furlong", "m"), None);
+        assert_eq!(convert_value(1.0, "m", "parsec"), None);
+    }
+
+    #[test]
+    fn queries() {
+        let conf = CalcConf::default();
+        let answer = convert("5 km in miles", &conf).unwrap();
+        assert_eq!(answer.expr, "5 km in miles");
+        assert_eq!(answer.value, "3.1068559612 miles");
+        assert_eq!(answer.plain, "3.1068559612");
+
+        assert_eq!(
+            convert("100 F to C", &conf).unwrap().value,
+            "37.7777777778 C"
+        );
+        assert_eq!(
+            convert("2 GiB in MB", &conf).unwrap().value,
+            "2,147.483648 MB"
+        );
+        assert_eq!(
+            convert("3 cups to ml", &conf).unwrap().value,
+            "709.7647095 ml"
+        );
+        assert_eq!(convert("5km as m", &conf).unwrap().value, "5,000 m");
+        assert_eq!(convert("5 in in cm", &conf).unwrap().value, "12.7 cm");
+        assert_eq!(convert("-40 C to F", &conf).unwrap().value, "-40 F");
+
+        assert!(convert("255 in hex", &conf).is_none());
+        assert!(convert("km in m", &conf).is_none());
+        assert!(convert("5 km", &conf).is_none());
+        assert!(convert("firefox", &conf).is_none());
+    }
+}
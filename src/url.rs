@@ -0,0 +1,258 @@
+use crate::app::AppEntry;
+use gtk::gio;
+use tracing::warn;
+
+// a bare `name.tld` is only taken for a url with one of these, `notes.txt`
+// is a file name
+const COMMON_TLDS: [&str; 24] = [
+    "com", "org", "net", "io", "dev", "app", "edu", "gov", "info", "co", "me", "ai", "uk", "de",
+    "fr", "es", "it", "nl", "jp", "cn", "ru", "ca", "us", "eu",
+];
+
+/// A url typed in the search box.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// with its scheme, `example.com` is `https://example.com`
+    pub url: String,
+    pub scheme: String,
+    /// default app of `x-scheme-handler/<scheme>`
+    pub handler: Option<AppEntry>,
+}
+
+impl Link {
+    pub fn mime_type(&self) -> String {
+        format!("x-scheme-handler/{}", self.scheme)
+    }
+
+    /// Opens the url with its scheme handler, or with whatever gio picks.
+    pub fn open(&self, term: String, term_launch_args: Vec<String>) {
+        if let Some(handler) = &self.handler {
+            handler.launch(term, term_launch_args, std::slice::from_ref(&self.url));
+            return;
+        }
+        if let Err(e) =
+            gio::AppInfo::launch_default_for_uri(&self.url, None::<&gio::AppLaunchContext>)
+        {
+            warn!("unable to open {}: {e}", self.url);
+        }
+    }
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+fn is_port(port: &str) -> bool {
+    (1..=5).contains(&port.len())
+        && port.chars().all(|c| c.is_ascii_digit())
+        && port.parse::<u32>().is_ok_and(|port| port <= 65535)
+}
+
+fn is_ipv4(host: &str) -> bool {
+    let parts: Vec<&str> = host.split('.').collect();
+    parts.len() == 4
+        && parts.iter().all(|part| {
+            (1..=3).contains(&part.len())
+                && part.chars().all(|c| c.is_ascii_digit())
+                && part.parse::<u32>().is_ok_and(|n| n <= 255)
+        })
+}
+
+fn is_ipv6(host: &str) -> bool {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .is_some_and(|host| {
+            host.contains(':')
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_hexdigit() || matches!(c, ':' | '.'))
+        })
+}
+
+/// `name.tld`, every label made of letters, digits and inner dashes.
+fn is_domain(host: &str) -> bool {
+    let labels: Vec<&str> = host.split('.').collect();
+    let tld = labels.last().copied().unwrap_or_default();
+    labels.len() >= 2
+        && tld.len() >= 2
+        && tld.chars().all(|c| c.is_ascii_alphabetic())
+        && labels.iter().all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Splits `host[:port]`, `None` when the port is not a port.
+fn split_port(authority: &str) -> Option<(&str, Option<&str>)> {
+    // the colons of an ipv6 address are not a port
+    let colon = match authority.rfind(']') {
+        Some(end) => authority[end..].find(':').map(|i| end + i),
+        None => authority.rfind(':'),
+    };
+    match colon {
+        Some(i) if is_port(&authority[i + 1..]) => {
+            Some((&authority[..i], Some(&authority[i + 1..])))
+        }
+        Some(_) => None,
+        None => Some((authority, None)),
+    }
+}
+
+fn is_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost") || is_ipv4(host) || is_ipv6(host) || is_domain(host)
+}
+
+/// The `host[:port]` part and what follows it.
+fn split_authority(rest: &str) -> (&str, &str) {
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    rest.split_at(end)
+}
+
+/// Recognizes `https://…`, `file:///…`, `mailto:…` and bare
+/// `example.com/path` urls.
+pub fn detect(query: &str) -> Option<Link> {
+    let query = query.trim();
+    if query.is_empty() || query.chars().any(char::is_whitespace) {
+        return None;
+    }
+    let link = |url: String, scheme: &str| Link {
+        url,
+        scheme: scheme.to_lowercase(),
+        handler: None,
+    };
+
+    if let Some((scheme, rest)) = query.split_once("://") {
+        if !is_scheme(scheme) {
+            return None;
+        }
+        let valid = match scheme.to_lowercase().as_str() {
+            // `file:///etc/hosts` or `file://host/path`
+            "file" => rest.starts_with('/') || split_authority(rest).1.starts_with('/'),
+            "http" | "https" | "ftp" | "ws" | "wss" => {
+                let (authority, _) = split_authority(rest);
+                // credentials in urls are mostly a phishing trick
+                !authority.contains('@')
+                    && split_port(authority).is_some_and(|(host, _)| is_host(host))
+            }
+            _ => !rest.is_empty(),
+        };
+        return valid.then(|| link(query.to_string(), scheme));
+    }
+
+    if let Some(address) = query.strip_prefix("mailto:") {
+        let (user, domain) = address.split_once('@')?;
+        let domain = domain.split('?').next().unwrap_or_default();
+        return (!user.is_empty() && is_domain(domain)).then(|| link(query.to_string(), "mailto"));
+    }
+
+    let (authority, path) = split_authority(query);
+    let (host, port) = split_port(authority)?;
+    let local = host.eq_ignore_ascii_case("localhost") || is_ipv4(host);
+    let explicit = port.is_some() || !path.is_empty();
+    let valid = if local {
+        explicit
+    } else {
+        is_domain(host) && {
+            let lowercase = host.to_lowercase();
+            let tld = lowercase.rsplit('.').next().unwrap_or_default();
+            explicit || lowercase.starts_with("www.") || COMMON_TLDS.contains(&tld)
+        }
+    };
+    if !valid {
+        return None;
+    }
+
+    // development servers rarely have a certificate
+    let scheme = if local { "http" } else { "https" };
+    Some(link(format!("{scheme}://{query}"), scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(query: &str) -> Option<String> {
+        detect(query).map(|link| link.url)
+    }
+
+    #[test]
+    fn full_urls() {
+        assert_eq!(
+            url("https://example.com/a?b=c#d").as_deref(),
+            Some("https://example.com/a?b=c#d")
+        );
+        assert_eq!(
+            url("http://localhost:8080").as_deref(),
+            Some("http://localhost:8080")
+        );
+        assert_eq!(
+            url("http://[::1]:3000/").as_deref(),
+            Some("http://[::1]:3000/")
+        );
+        assert_eq!(
+            url("file:///etc/hosts").as_deref(),
+            Some("file:///etc/hosts")
+        );
+        assert_eq!(detect("HTTPS://Example.com").unwrap().scheme, "https");
+        assert_eq!(detect("steam://run/440").unwrap().scheme, "steam");
+    }
+
+    #[test]
+    fn bare_urls() {
+        assert_eq!(url("example.com").as_deref(), Some("https://example.com"));
+        assert_eq!(
+            url("example.org/path").as_deref(),
+            Some("https://example.org/path")
+        );
+        assert_eq!(
+            url("www.example.xyz").as_deref(),
+            Some("https://www.example.xyz")
+        );
+        assert_eq!(
+            url("docs.rs/tokio").as_deref(),
+            Some("https://docs.rs/tokio")
+        );
+        assert_eq!(
+            url("192.168.1.1:8080").as_deref(),
+            Some("http://192.168.1.1:8080")
+        );
+        assert_eq!(
+            url("localhost:3000/api").as_deref(),
+            Some("http://localhost:3000/api")
+        );
+    }
+
+    #[test]
+    fn mailto() {
+        assert_eq!(detect("mailto:me@example.com").unwrap().scheme, "mailto");
+        assert_eq!(detect("mailto:example.com"), None);
+    }
+
+    #[test]
+    fn rejects_non_urls() {
+        for query in [
+            "",
+            "firefox",
+            "notes.txt",
+            "3.14",
+            "1.5 km in m",
+            "example .com",
+            "https://",
+            "https://exa_mple.com",
+            "https://user@example.com",
+            "http://example.com:99999",
+            "-example.com/",
+            "example.c/",
+            "192.168.1.1",
+            "localhost",
+            "1://example.com",
+            "file://relative",
+        ] {
+            assert_eq!(url(query), None, "{query}");
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use gtk::gio;
+use tracing::warn;
+
+/// A `[websearch]` engine, `g = Google https://www.google.com/search?q=%s`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Engine {
+    pub keyword: String,
+    pub name: String,
+    /// `%s` stands for the percent-encoded terms
+    pub template: String,
+}
+
+impl Engine {
+    /// Parses `<name> <template>`, the template being the last word.
+    pub fn parse(keyword: &str, val: &str) -> Option<Self> {
+        let (name, template) = val.trim().rsplit_once(char::is_whitespace)?;
+        if keyword.is_empty() || !template.contains("%s") {
+            return None;
+        }
+        Some(Self {
+            keyword: keyword.to_string(),
+            name: name.trim().to_string(),
+            template: template.to_string(),
+        })
+    }
+
+    pub fn url(&self, terms: &str) -> String {
+        self.template.replace("%s", &percent_encode(terms))
+    }
+}
+
+/// The `[websearch]` section.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebSearchConf {
+    pub engines: Vec<Engine>,
+    /// keyword of the engine offered when nothing else matches
+    pub fallback: Option<String>,
+}
+
+impl Default for WebSearchConf {
+    fn default() -> Self {
+        let engine = |keyword: &str, val: &str| Engine::parse(keyword, val).expect("valid engine");
+        Self {
+            engines: vec![
+                engine("g", "Google https://www.google.com/search?q=%s"),
+                engine("ddg", "DuckDuckGo https://duckduckgo.com/?q=%s"),
+                engine(
+                    "w",
+                    "Wikipedia https://en.wikipedia.org/w/index.php?search=%s",
+                ),
+            ],
+            fallback: None,
+        }
+    }
+}
+
+impl WebSearchConf {
+    /// Adds `engine`, replacing a default one with the same keyword.
+    pub fn add(&mut self, engine: Engine) {
+        self.engines.retain(|known| known.keyword != engine.keyword);
+        self.engines.push(engine);
+    }
+
+    fn engine(&self, keyword: &str) -> Option<&Engine> {
+        self.engines.iter().find(|engine| engine.keyword == keyword)
+    }
+
+    pub fn fallback(&self, query: &str) -> Option<WebSearch> {
+        let engine = self.engine(self.fallback.as_deref()?)?;
+        WebSearch::new(engine, query.trim())
+    }
+}
+
+/// Escapes everything but the unreserved characters of RFC 3986.
+pub fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// A web search ready to be opened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebSearch {
+    pub engine: String,
+    pub terms: String,
+    pub url: String,
+}
+
+impl WebSearch {
+    fn new(engine: &Engine, terms: &str) -> Option<Self> {
+        (!terms.is_empty()).then(|| Self {
+            engine: engine.name.clone(),
+            terms: terms.to_string(),
+            url: engine.url(terms),
+        })
+    }
+
+    /// Opens the search in the default browser.
+    pub fn open(&self) {
+        if let Err(e) =
+            gio::AppInfo::launch_default_for_uri(&self.url, None::<&gio::AppLaunchContext>)
+        {
+            warn!("unable to open {}: {e}", self.url);
+        }
+    }
+}
+
+/// `g rust lifetimes`, `!ddg foo` or `foo !ddg`.
+pub fn detect(query: &str, conf: &WebSearchConf) -> Option<WebSearch> {
+    let query = query.trim();
+    let (first, rest) = query.split_once(char::is_whitespace)?;
+    if let Some(engine) = conf.engine(first.strip_prefix('!').unwrap_or(first)) {
+        return WebSearch::new(engine, rest.trim());
+    }
+
+    let (rest, last) = query.rsplit_once(char::is_whitespace)?;
+    let engine = conf.engine(last.strip_prefix('!')?)?;
+    WebSearch::new(engine, rest.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(query: &str) -> Option<String> {
+        detect(query, &WebSearchConf::default()).map(|search| search.url)
+    }
+
+    #[test]
+    fn keywords_and_bangs() {
+        assert_eq!(
+            url("g rust lifetimes").as_deref(),
+            Some("https://www.google.com/search?q=rust%20lifetimes")
+        );
+        assert_eq!(
+            url("!ddg foo").as_deref(),
+            Some("https://duckduckgo.com/?q=foo")
+        );
+        assert_eq!(
+            url("foo bar !w").as_deref(),
+            Some("https://en.wikipedia.org/w/index.php?search=foo%20bar")
+        );
+        assert_eq!(url("g"), None);
+        assert_eq!(url("g   "), None);
+        assert_eq!(url("firefox"), None);
+        assert_eq!(url("gimp editor"), None);
+        assert_eq!(url("foo w"), None);
+    }
+
+    #[test]
+    fn encoding() {
+        assert_eq!(percent_encode("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(percent_encode("c++ & rust?"), "c%2B%2B%20%26%20rust%3F");
+        assert_eq!(percent_encode("café/100%"), "caf%C3%A9%2F100%25");
+    }
+
+    #[test]
+    fn configured_engines() {
+        let mut conf = WebSearchConf::default();
+        conf.add(Engine::parse("g", "Google FR https://www.google.fr/search?q=%s").unwrap());
+        conf.add(Engine::parse("gh", "GitHub Code https://github.com/search?q=%s").unwrap());
+        conf.fallback = Some("gh".to_string());
+
+        let search = detect("g test", &conf).unwrap();
+        assert_eq!(search.engine, "Google FR");
+        assert_eq!(search.url, "https://www.google.fr/search?q=test");
+        assert_eq!(
+            conf.fallback(" tokio select ").unwrap().url,
+            "https://github.com/search?q=tokio%20select"
+        );
+        assert_eq!(Engine::parse("x", "https://example.com/?q=%s"), None);
+        assert_eq!(Engine::parse("x", "Example https://example.com/"), None);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::app::AppEntry;
+use crate::conf;
+use serde_json::Value;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+const IPC_TIMEOUT: Duration = Duration::from_secs(1);
+const I3_IPC_MAGIC: &[u8] = b"i3-ipc";
+const SWAY_RUN_COMMAND: u32 = 0;
+const SWAY_GET_TREE: u32 = 4;
+
+/// The compositor seekr runs under, with its ipc socket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Compositor {
+    Sway(PathBuf),
+    Hyprland(PathBuf),
+}
+
+/// An open window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    /// sway container id or hyprland address
+    pub id: String,
+    pub title: String,
+    /// wayland app id, or the class of xwayland windows
+    pub app_id: String,
+    pub workspace: Option<String>,
+    /// icon of the app owning the window, filled in once it is a result
+    pub icon: Option<String>,
+    pub compositor: Compositor,
+}
+
+impl Window {
+    pub fn focus(&self) {
+        let focused = match &self.compositor {
+            Compositor::Sway(socket) => {
+                let command = format!("[con_id={}] focus", self.id);
+                sway_request(socket, SWAY_RUN_COMMAND, &command).and_then(|reply| {
+                    let success = reply
+                        .as_array()
+                        .and_then(|results| results.first())
+                        .and_then(|result| result.get("success"))
+                        .and_then(Value::as_bool);
+                    match success {
+                        Some(true) => Ok(()),
+                        _ => Err(io::Error::other(reply.to_string())),
+                    }
+                })
+            }
+            Compositor::Hyprland(socket) => {
+                let command = format!("dispatch focuswindow address:{}", self.id);
+                hyprland_request(socket, &command).and_then(|reply| match reply.trim() {
+                    "ok" => Ok(()),
+                    reply => Err(io::Error::other(reply.to_string())),
+                })
+            }
+        };
+        if let Err(e) = focused {
+            warn!("unable to focus {:?}: {e}", self.title);
+        }
+    }
+
+    /// The installed app the window belongs to, by desktop file id.
+    pub fn app<'a>(&self, entries: &'a [AppEntry]) -> Option<&'a AppEntry> {
+        let app_id = self.app_id.to_lowercase();
+        // `org.gnome.Nautilus` and `firefox` are ids, `Firefox` a class
+        entries.iter().find(|entry| {
+            let id = entry.id.to_lowercase();
+            id == app_id || id.rsplit('.').next() == Some(app_id.as_str())
+        })
+    }
+}
+
+impl Compositor {
+    /// Looks for the sway or hyprland socket in the environment.
+    pub fn detect() -> Option<Self> {
+        if let Some(socket) = std::env::var_os("SWAYSOCK") {
+            return Some(Compositor::Sway(PathBuf::from(socket)));
+        }
+
+        let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+        // the socket moved from /tmp to the runtime directory in 0.40
+        let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_default();
+        [PathBuf::from(runtime_dir), PathBuf::from("/tmp")]
+            .into_iter()
+            .map(|dir| dir.join("hypr").join(&signature).join(".socket.sock"))
+            .find(|socket| socket.exists())
+            .map(Compositor::Hyprland)
+    }
+
+    /// The windows of other apps, the most recently focused first.
+    pub fn windows(&self) -> io::Result<Vec<Window>> {
+        let mut windows = match self {
+            Compositor::Sway(socket) => {
+                let tree = sway_request(socket, SWAY_GET_TREE, "")?;
+                let mut windows = vec![];
+                sway_windows(self, &tree, None, &mut windows);
+                windows
+            }
+            Compositor::Hyprland(socket) => {
+                let clients: Value = serde_json::from_str(&hyprland_request(socket, "j/clients")?)?;
+                hyprland_windows(self, &clients)
+            }
+        };
+        windows.retain(|window| window.app_id != conf::APP_ID);
+        Ok(windows)
+    }
+}
+
+/// The open windows, none outside of sway and hyprland.
+pub fn load() -> Vec<Window> {
+    let Some(compositor) = Compositor::detect() else {
+        return vec![];
+    };
+    match compositor.windows() {
+        Ok(windows) => {
+            debug!("found {} windows", windows.len());
+            windows
+        }
+        Err(e) => {
+            warn!("unable to list windows: {e}");
+            vec![]
+        }
+    }
+}
+
+fn connect(socket: &Path) -> io::Result<UnixStream> {
+    let stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+    stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Sends an i3-ipc message, `magic, length, type, payload` with native
+/// endian integers, and reads the reply of the same shape.
+fn sway_request(socket: &Path, kind: u32, payload: &str) -> io::Result<Value> {
+    let mut stream = connect(socket)?;
+    let mut message = I3_IPC_MAGIC.to_vec();
+    message.extend((payload.len() as u32).to_ne_bytes());
+    message.extend(kind.to_ne_bytes());
+    message.extend(payload.as_bytes());
+    stream.write_all(&message)?;
+
+    let mut header = [0; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != I3_IPC_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an i3-ipc reply",
+        ));
+    }
+    let len = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+    let mut body = vec![0; len as usize];
+    stream.read_exact(&mut body)?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Windows are the leaves of the tree, tiled or floating. Each node lists
+/// its children by focus, walking them in that order puts the most
+/// recently focused windows first.
+fn sway_windows(
+    compositor: &Compositor,
+    node: &Value,
+    workspace: Option<&str>,
+    windows: &mut Vec<Window>,
+) {
+    let field = |key: &str| node.get(key).and_then(Value::as_str);
+    let workspace = match field("type") {
+        Some("workspace") => field("name"),
+        _ => workspace,
+    };
+
+    let mut children: Vec<&Value> = ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node.get(*key).and_then(Value::as_array))
+        .flatten()
+        .collect();
+    let focus: Vec<i64> = node
+        .get("focus")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_i64)
+        .collect();
+    children.sort_by_key(|child| {
+        let id = child.get("id").and_then(Value::as_i64);
+        focus
+            .iter()
+            .position(|focused| Some(*focused) == id)
+            .unwrap_or(focus.len())
+    });
+    let is_window = matches!(field("type"), Some("con" | "floating_con"))
+        && children.is_empty()
+        && node.get("pid").is_some();
+    if is_window {
+        let class = node
+            .get("window_properties")
+            .and_then(|props| props.get("class"))
+            .and_then(Value::as_str);
+        if let Some(id) = node.get("id").and_then(Value::as_i64) {
+            windows.push(Window {
+                id: id.to_string(),
+                title: field("name").unwrap_or_default().to_string(),
+                app_id: field("app_id").or(class).unwrap_or_default().to_string(),
+                // the scratchpad is a workspace of its own
+                workspace: workspace
+                    .filter(|name| *name != "__i3_scratch")
+                    .map(str::to_string),
+                icon: None,
+                compositor: compositor.clone(),
+            });
+        }
+    }
+
+    for child in children {
+        sway_windows(compositor, child, workspace, windows);
+    }
+}
+
+/// Writes a command on the hyprland socket, the reply ends with the
+/// connection.
+fn hyprland_request(socket: &Path, command: &str) -> io::Result<String> {
+    let mut stream = connect(socket)?;
+    stream.write_all(command.as_bytes())?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// `focusHistoryID` counts from the focused window.
+fn hyprland_windows(compositor: &Compositor, clients: &Value) -> Vec<Window> {
+    let mut clients: Vec<&Value> = clients
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|client| client.get("mapped").and_then(Value::as_bool) != Some(false))
+        .collect();
+    clients.sort_by_key(|client| {
+        client
+            .get("focusHistoryID")
+            .and_then(Value::as_i64)
+            .unwrap_or(i64::MAX)
+    });
+    clients
+        .into_iter()
+        .filter_map(|client| {
+            let field = |key: &str| client.get(key).and_then(Value::as_str);
+            Some(Window {
+                id: field("address")?.to_string(),
+                title: field("title").unwrap_or_default().to_string(),
+                app_id: field("class")
+                    .filter(|class| !class.is_empty())
+                    .or(field("initialClass"))
+                    .unwrap_or_default()
+                    .to_string(),
+                workspace: client
+                    .get("workspace")
+                    .and_then(|workspace| workspace.get("name"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                icon: None,
+                compositor: compositor.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("seekr-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Answers one i3-ipc message per connection, returns what was asked.
+    fn fake_sway(
+        path: &Path,
+        replies: Vec<&'static str>,
+    ) -> thread::JoinHandle<Vec<(u32, String)>> {
+        let listener = UnixListener::bind(path).unwrap();
+        thread::spawn(move || {
+            let mut requests = vec![];
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut header = [0; 14];
+                stream.read_exact(&mut header).unwrap();
+                assert_eq!(&header[..6], I3_IPC_MAGIC);
+                let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+                let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+                let mut payload = vec![0; len as usize];
+                stream.read_exact(&mut payload).unwrap();
+                requests.push((kind, String::from_utf8(payload).unwrap()));
+
+                let mut message = I3_IPC_MAGIC.to_vec();
+                message.extend((reply.len() as u32).to_ne_bytes());
+                message.extend(kind.to_ne_bytes());
+                message.extend(reply.as_bytes());
+                stream.write_all(&message).unwrap();
+            }
+            requests
+        })
+    }
+
+    /// Answers one command per connection and hangs up.
+    fn fake_hyprland(path: &Path, replies: Vec<&'static str>) -> thread::JoinHandle<Vec<String>> {
+        let listener = UnixListener::bind(path).unwrap();
+        thread::spawn(move || {
+            let mut requests = vec![];
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 256];
+                let len = stream.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..len]).to_string());
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            requests
+        })
+    }
+
+    const SWAY_TREE: &str = r#"{"id": 1, "type": "root", "name": "root", "nodes": [
+        {"id": 2, "type": "output", "name": "eDP-1", "nodes": [
+            {"id": 3, "type": "workspace", "name": "1", "focus": [4, 12, 10], "nodes": [
+                {"id": 10, "type": "con", "name": "~/crate - nvim", "app_id": "foot", "pid": 100, "nodes": []},
+                {"id": 4, "type": "con", "name": null, "focus": [13, 11], "nodes": [
+                    {"id": 11, "type": "con", "name": "Steam", "app_id": null, "pid": 101,
+                     "window_properties": {"class": "steam"}, "nodes": []},
+                    {"id": 13, "type": "con", "name": "seekr", "app_id": "dev.luxluth.seekr", "pid": 103, "nodes": []}
+                ]}
+            ], "floating_nodes": [
+                {"id": 12, "type": "floating_con", "name": "Picture-in-Picture", "app_id": "firefox", "pid": 102, "nodes": []}
+            ]}
+        ]}
+    ]}"#;
+
+    #[test]
+    fn sway() {
+        let path = socket_path("sway.sock");
+        let server = fake_sway(&path, vec![SWAY_TREE, r#"[{"success": true}]"#]);
+        let compositor = Compositor::Sway(path.clone());
+
+        let windows = compositor.windows().unwrap();
+        let listed: Vec<(&str, &str, &str)> = windows
+            .iter()
+            .map(|window| {
+                (
+                    window.id.as_str(),
+                    window.app_id.as_str(),
+                    window.title.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("11", "steam", "Steam"),
+                ("12", "firefox", "Picture-in-Picture"),
+                ("10", "foot", "~/crate - nvim"),
+            ]
+        );
+        assert!(windows
+            .iter()
+            .all(|window| window.workspace.as_deref() == Some("1")));
+
+        windows[1].focus();
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0], (SWAY_GET_TREE, String::new()));
+        assert_eq!(
+            requests[1],
+            (SWAY_RUN_COMMAND, "[con_id=12] focus".to_string())
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn hyprland() {
+        let clients = r#"[
+            {"address": "0x55d1", "mapped": true, "hidden": false, "workspace": {"id": 2, "name": "2"},
+             "class": "org.gnome.Nautilus", "title": "Downloads", "initialClass": "org.gnome.Nautilus",
+             "focusHistoryID": 2},
+            {"address": "0x55d2", "mapped": false, "workspace": {"id": -1, "name": ""},
+             "class": "", "title": "", "initialClass": "", "focusHistoryID": 3},
+            {"address": "0x55d3", "mapped": true, "workspace": {"id": 1, "name": "1"},
+             "class": "", "title": "Spotify Premium", "initialClass": "Spotify", "focusHistoryID": 1},
+            {"address": "0x55d4", "mapped": true, "workspace": {"id": 1, "name": "1"},
+             "class": "dev.luxluth.seekr", "title": "seekr", "initialClass": "dev.luxluth.seekr",
+             "focusHistoryID": 0}
+        ]"#;
+        let path = socket_path("hypr.sock");
+        let server = fake_hyprland(&path, vec![clients, "ok"]);
+        let compositor = Compositor::Hyprland(path.clone());
+
+        let windows = compositor.windows().unwrap();
+        let listed: Vec<(&str, &str, Option<&str>)> = windows
+            .iter()
+            .map(|window| {
+                (
+                    window.id.as_str(),
+                    window.app_id.as_str(),
+                    window.workspace.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("0x55d3", "Spotify", Some("1")),
+                ("0x55d1", "org.gnome.Nautilus", Some("2")),
+            ]
+        );
+
+        windows[1].focus();
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests,
+            ["j/clients", "dispatch focuswindow address:0x55d1"]
+        );
+        let _ = std::fs::remove_file(path);
+    }
+}